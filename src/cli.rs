@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: knurling [--config PATH] [--name NAME]
+       knurling --check [--config PATH]
+       knurling --print-default-config
+       knurling msg [--name NAME] <request> [args...]
+
+options:
+  -c, --config PATH         use PATH instead of $XDG_CONFIG_HOME/knurling/knurling.toml
+  -n, --name NAME           what `knurling msg --name` calls this bar (the
+                            default is the name of the `--config` file)
+      --check               check that the config is valid and exit
+      --print-default-config
+                            print an annotated starter config and exit
+  -h, --help                show this message and exit
+
+requests for `msg`:
+  set <widget-id> <text>, reload, hide, show, toggle, quit, dump-state
+";
+
+/// What we've been asked to do on the command line
+#[derive(Debug)]
+pub enum Command {
+    Run {
+        config: Option<PathBuf>,
+        name: Option<String>,
+    },
+    Check {
+        config: Option<PathBuf>,
+    },
+    PrintDefaultConfig,
+    Msg {
+        name: Option<String>,
+        args: Vec<String>,
+    },
+    Help,
+}
+
+impl Command {
+    pub fn parse(args: &[String]) -> Result<Command, failure::Error> {
+        // `msg` takes everything after it verbatim, because the text
+        // people send along might well look like a flag
+        // (apart from picking which bar to talk to)
+        if args.first().map(|s| s.as_str()) == Some("msg") {
+            let mut args = &args[1..];
+            let mut name = None;
+            match args.first().map(|s| s.as_str()) {
+                Some("-n") | Some("--name") => {
+                    name = Some(
+                        args.get(1)
+                            .ok_or_else(|| format_err!("`{}` needs a name after it", args[0]))?
+                            .clone(),
+                    );
+                    args = &args[2..];
+                }
+                Some(arg) if arg.starts_with("--name=") => {
+                    name = Some(arg["--name=".len()..].to_string());
+                    args = &args[1..];
+                }
+                _ => (),
+            }
+            return Ok(Command::Msg {
+                name,
+                args: args.to_vec(),
+            });
+        }
+
+        let mut config = None;
+        let mut name = None;
+        let mut check = false;
+        let mut print_default = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| format_err!("`{}` needs a path after it", arg))?;
+                    config = Some(PathBuf::from(path));
+                }
+                _ if arg.starts_with("--config=") => {
+                    config = Some(PathBuf::from(&arg["--config=".len()..]));
+                }
+                "-n" | "--name" => {
+                    let n = args
+                        .next()
+                        .ok_or_else(|| format_err!("`{}` needs a name after it", arg))?;
+                    name = Some(n.clone());
+                }
+                _ if arg.starts_with("--name=") => {
+                    name = Some(arg["--name=".len()..].to_string());
+                }
+                "--check" => check = true,
+                "--print-default-config" => print_default = true,
+                "-h" | "--help" => return Ok(Command::Help),
+                _ => bail!("unexpected argument {:?}", arg),
+            }
+        }
+
+        if name.is_some() && (check || print_default) {
+            bail!("`--name` is only for running a bar");
+        }
+        match (check, print_default) {
+            (true, true) => bail!("`--check` and `--print-default-config` don't go together"),
+            (true, false) => Ok(Command::Check { config }),
+            (false, true) if config.is_some() => {
+                bail!("`--print-default-config` doesn't use `--config`")
+            }
+            (false, true) => Ok(Command::PrintDefaultConfig),
+            (false, false) => Ok(Command::Run { config, name }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, failure::Error> {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        Command::parse(&args)
+    }
+
+    #[test]
+    fn run_with_a_name() {
+        match parse(&["--config", "a.toml", "--name", "top"]).unwrap() {
+            Command::Run { config, name } => {
+                assert_eq!(config, Some(PathBuf::from("a.toml")));
+                assert_eq!(name.as_deref(), Some("top"));
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+        assert!(parse(&["--check", "--name", "top"]).is_err());
+    }
+
+    #[test]
+    fn msg_picks_a_bar() {
+        match parse(&["msg", "--name", "top", "set", "--name", "x"]).unwrap() {
+            Command::Msg { name, args } => {
+                assert_eq!(name.as_deref(), Some("top"));
+                assert_eq!(args, vec!["set", "--name", "x"]);
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+        match parse(&["msg", "reload"]).unwrap() {
+            Command::Msg { name, args } => {
+                assert_eq!(name, None);
+                assert_eq!(args, vec!["reload"]);
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+    }
+}
@@ -1,4 +1,10 @@
 use crate::widgets as w;
+use crate::window::Monitor;
+use crate::worker::{Waker, Worker};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::sync::Arc;
 use std::time;
 
 mod defaults {
@@ -7,44 +13,456 @@ mod defaults {
 
     pub const FONT_FAMILY: &str = "Fira Mono";
     pub const FONT_SIZE: &str = "18";
+
+    pub const UPDATE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    pub const ERROR_COLOR: (f64, f64, f64) = (1.0, 0.3, 0.3);
+    pub const FLASH_DURATION: std::time::Duration = std::time::Duration::from_secs(10);
+}
+
+/// The config we use if there isn't one on disk, which is also what
+/// `--print-default-config` prints
+pub const DEFAULT_CONFIG: &str = include_str!("default.toml");
+
+/// The gap between widgets (unless they ask for a different
+/// `margin`), and between widgets and the ends of the bar
+const SPACING: i32 = 10;
+
+/// How often to redraw while there's text scrolling past
+const SCROLL_TICK: time::Duration = time::Duration::from_millis(50);
+
+/// Shrink the widest of `widths` until they fit into `budget`, along
+/// with the `gaps` between them. Usually there's one big thing (like
+/// a window title) that's responsible for not fitting, so it makes
+/// sense to take the room back from that first.
+fn fit(widths: &mut [i32], gaps: i32, budget: i32) {
+    let mut excess = widths.iter().sum::<i32>() + gaps - budget;
+    while excess > 0 {
+        let widest = match widths.iter_mut().max_by_key(|wd| **wd) {
+            Some(wd) if *wd > 0 => wd,
+            _ => return,
+        };
+        let cut = excess.min(*widest);
+        *widest -= cut;
+        excess -= cut;
+    }
 }
 
 pub struct Config {
-    left: Vec<WidgetWrapper>,
-    right: Vec<WidgetWrapper>,
+    // the first of these is what every monitor gets, unless there's
+    // one just for it
+    bars: Vec<Bar>,
+    // which monitors get a bar at all, if it's not all of them
+    monitors: Option<Vec<OutputName>>,
     bg_color: (f64, f64, f64),
     fg_color: (f64, f64, f64),
     font: String,
     height: i32,
     buffer: i32,
+    position: Position,
+    // how far the bar sits from the sides of the screen and from the
+    // edge it's on, for bars that float
+    margin_x: i32,
+    margin_y: i32,
+    waker: Arc<Waker>,
+    // an error message to show in the bar for a little while,
+    // e.g. when a reload didn't work out
+    flash: Option<(String, time::Instant)>,
+    // whether anything got drawn scrolling the last time around, in
+    // which case we need to keep redrawing it
+    scrolling: Cell<bool>,
+}
+
+/// Which part of the bar a widget lives in
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Section {
+    Left,
+    #[serde(alias = "centre")]
+    Center,
+    Right,
+}
+
+/// The widgets that make up one bar
+#[derive(Default)]
+struct Bar {
+    // the monitor this bar is for, if it's not the default one
+    output: Option<OutputName>,
+    left: Vec<WidgetWrapper>,
+    center: Vec<WidgetWrapper>,
+    right: Vec<WidgetWrapper>,
+}
+
+impl Bar {
+    /// Put the widgets from the config into sections and start them
+    /// running
+    fn new(output: Option<OutputName>, entries: Vec<Entry>, waker: &Arc<Waker>) -> Bar {
+        let mut bar = Bar {
+            output,
+            ..Bar::default()
+        };
+        // with one `sep` we've just got a left and a right, but a
+        // second one means there's something in the middle too
+        let seps = entries.iter().filter(|e| matches!(e, Entry::Sep)).count();
+        let mut target = Section::Left;
+        let mut companions = Vec::new();
+        for entry in entries {
+            match entry {
+                Entry::Sep if target == Section::Left && seps > 1 => target = Section::Center,
+                Entry::Sep => target = Section::Right,
+                Entry::Widget(widget, options) => {
+                    let section = options.align.unwrap_or(target);
+                    // these go at the far end of their sections, and
+                    // share the text settings of the widget they came
+                    // from
+                    for (section, companion) in widget.companions() {
+                        let options = WidgetOptions {
+                            name: options.name.clone(),
+                            max_width: options.max_width,
+                            overflow: options.overflow,
+                            markup: options.markup,
+                            foreground: options.foreground,
+                            background: options.background,
+                            padding_left: options.padding_left,
+                            padding_right: options.padding_right,
+                            margin: options.margin,
+                            ..WidgetOptions::default()
+                        };
+                        let wr = WidgetWrapper::new(companion, options, waker);
+                        companions.push((section, wr));
+                    }
+                    let wr = WidgetWrapper::new(widget, *options, waker);
+                    bar.section_mut(section).push(wr);
+                }
+            }
+        }
+        for (section, wr) in companions {
+            bar.section_mut(section).push(wr);
+        }
+        bar
+    }
+
+    fn section(&self, section: Section) -> &Vec<WidgetWrapper> {
+        match section {
+            Section::Left => &self.left,
+            Section::Center => &self.center,
+            Section::Right => &self.right,
+        }
+    }
+
+    fn section_mut(&mut self, section: Section) -> &mut Vec<WidgetWrapper> {
+        match section {
+            Section::Left => &mut self.left,
+            Section::Center => &mut self.center,
+            Section::Right => &mut self.right,
+        }
+    }
+
+    /// Every widget in the bar, from left to right
+    fn widgets(&self) -> impl Iterator<Item = &WidgetWrapper> {
+        self.left
+            .iter()
+            .chain(self.center.iter())
+            .chain(self.right.iter())
+    }
+
+    fn widgets_mut(&mut self) -> impl Iterator<Item = &mut WidgetWrapper> {
+        self.left
+            .iter_mut()
+            .chain(self.center.iter_mut())
+            .chain(self.right.iter_mut())
+    }
+}
+
+/// A way of picking out a monitor in the config: by the name of its
+/// output (e.g. `"DP-1"`), by its position in the list of monitors
+/// (starting from 0), or as whichever one is `"primary"`
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputName {
+    Primary,
+    Index(usize),
+    Name(String),
+}
+
+impl OutputName {
+    fn from_key(key: &str) -> OutputName {
+        match key.parse() {
+            Ok(index) => OutputName::Index(index),
+            Err(_) if key == "primary" => OutputName::Primary,
+            Err(_) => OutputName::Name(key.to_string()),
+        }
+    }
+
+    fn matches(&self, index: usize, monitor: &Monitor) -> bool {
+        match self {
+            OutputName::Primary => monitor.primary,
+            OutputName::Index(i) => *i == index,
+            OutputName::Name(name) => monitor.name.as_deref() == Some(name),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputName {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<OutputName, D::Error> {
+        struct Visitor;
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = OutputName;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "an output name, a monitor number, or \"primary\"")
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, n: i64) -> Result<OutputName, E> {
+                match usize::try_from(n) {
+                    Ok(n) => Ok(OutputName::Index(n)),
+                    Err(_) => Err(E::invalid_value(serde::de::Unexpected::Signed(n), &self)),
+                }
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<OutputName, E> {
+                Ok(OutputName::from_key(s))
+            }
+        }
+        d.deserialize_any(Visitor)
+    }
+}
+
+/// Which edge of the screen the bar goes along
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Position {
+    Top,
+    Bottom,
+}
+
+/// Where a widget ended up the last time we drew the bar, so we can
+/// figure out which one a click was meant for
+#[derive(Debug, Clone, Copy)]
+pub struct Extent {
+    bar: usize,
+    section: Section,
+    index: usize,
+    x: f64,
+    wd: f64,
 }
 
 pub struct WidgetWrapper {
-    update: Option<(time::Duration, time::SystemTime)>,
-    widget: Box<dyn w::Widget>,
+    name: String,
+    // an optional name for this specific widget, so that it can be
+    // referred to from outside (e.g. by `knurling msg set`)
+    id: Option<String>,
+    update: Option<(w::Frequency, time::Instant)>,
+    worker: Option<Worker>,
+    // how long an update can take before we start showing the
+    // widget as stale
+    timeout: time::Duration,
+    actions: Vec<(w::Button, String)>,
+    max_width: Option<w::MaxWidth>,
+    overflow: w::Overflow,
+    markup: bool,
+    // colors to use instead of the bar's own
+    foreground: Option<(f64, f64, f64)>,
+    background: Option<(f64, f64, f64)>,
+    // the room to leave inside the widget's background on either
+    // side, and the gap to leave between it and its neighbours
+    padding: (i32, i32),
+    margin: i32,
+    widget: Arc<dyn w::Widget>,
+}
+
+/// The settings that every widget has, regardless of what kind of
+/// widget it is
+#[derive(Default, Deserialize)]
+struct WidgetOptions {
+    name: String,
+    // an optional name for this specific widget, so that it can be
+    // referred to from outside (e.g. by `knurling msg set`)
+    id: Option<String>,
+    // how long an update can take before we start showing the
+    // widget as stale
+    update_timeout: Option<w::Seconds>,
+    // which part of the bar this goes in, if it's somewhere other
+    // than where the `sep`s put it
+    align: Option<Section>,
+    // the most room it gets, and what to do with text if it needs
+    // more than that
+    max_width: Option<w::MaxWidth>,
+    overflow: Option<w::Overflow>,
+    // whether its text is Pango markup
+    markup: Option<bool>,
+    // colors just for this widget, with the background filling in
+    // behind the widget and its padding
+    foreground: Option<Color>,
+    background: Option<Color>,
+    padding_left: Option<u16>,
+    padding_right: Option<u16>,
+    // the gap between this widget and the ones next to it
+    margin: Option<u16>,
+    // commands to run when the widget gets clicked or scrolled on
+    on_click_left: Option<String>,
+    on_click_middle: Option<String>,
+    on_click_right: Option<String>,
+    on_scroll_up: Option<String>,
+    on_scroll_down: Option<String>,
+}
+
+impl WidgetOptions {
+    fn actions(&self) -> Vec<(w::Button, String)> {
+        let actions = [
+            (w::Button::Left, &self.on_click_left),
+            (w::Button::Middle, &self.on_click_middle),
+            (w::Button::Right, &self.on_click_right),
+            (w::Button::ScrollUp, &self.on_scroll_up),
+            (w::Button::ScrollDown, &self.on_scroll_down),
+        ];
+        actions
+            .iter()
+            .filter_map(|(button, command)| command.as_ref().map(|c| (*button, c.clone())))
+            .collect()
+    }
 }
 
 impl WidgetWrapper {
-    fn new(mut widget: Box<dyn w::Widget>) -> WidgetWrapper {
-        let update = if let Some(f) = widget.update_frequency() {
-            widget.update();
-            Some((time::Duration::new(f, 0), time::SystemTime::now()))
+    fn new(
+        widget: Box<dyn w::Widget>,
+        options: WidgetOptions,
+        waker: &Arc<Waker>,
+    ) -> WidgetWrapper {
+        let widget: Arc<dyn w::Widget> = Arc::from(widget);
+        let update = widget.update_frequency().map(|f| {
+            let now = time::Instant::now();
+            (f, f.next_deadline(now))
+        });
+        // kick off the first update right away, but in the background
+        // if it's slow, so that we can draw the bar in the meantime
+        let worker = if update.is_none() {
+            None
+        } else if widget.update_blocks() {
+            let mut worker = Worker::spawn(widget.clone(), waker.clone());
+            worker.request();
+            Some(worker)
         } else {
+            widget.update();
             None
         };
-        WidgetWrapper { update, widget }
+        WidgetWrapper {
+            actions: options.actions(),
+            max_width: options.max_width,
+            overflow: options.overflow.unwrap_or(w::Overflow::End),
+            markup: options.markup.unwrap_or(false),
+            foreground: options.foreground.map(|c| c.0),
+            background: options.background.map(|c| c.0),
+            padding: (
+                options.padding_left.map_or(0, i32::from),
+                options.padding_right.map_or(0, i32::from),
+            ),
+            margin: options.margin.map_or(SPACING, i32::from),
+            name: options.name,
+            id: options.id,
+            update,
+            worker,
+            timeout: options
+                .update_timeout
+                .map_or(defaults::UPDATE_TIMEOUT, |t| t.0),
+            widget,
+        }
+    }
+
+    fn deadline(&self) -> Option<time::Instant> {
+        let next_update = self.update.map(|(_, deadline)| deadline);
+        // if an update is taking a while, then we also want to wake
+        // up when it's time to mark it as stale
+        let now = time::Instant::now();
+        let overdue = self
+            .worker
+            .as_ref()
+            .and_then(|wk| wk.overdue_at(self.timeout))
+            .filter(|t| *t > now);
+        next_update.into_iter().chain(overdue).min()
     }
 
-    fn update(&mut self) {
-        if let Some((freq, ref mut last)) = self.update {
-            if let Ok(since) = last.elapsed() {
-                if since > freq {
-                    self.widget.update();
-                    *last = time::SystemTime::now();
+    fn is_stale(&self) -> bool {
+        self.worker
+            .as_ref()
+            .and_then(|wk| wk.overdue_at(self.timeout))
+            .is_some_and(|t| time::Instant::now() >= t)
+    }
+
+    fn update(&mut self, now: time::Instant) {
+        if let Some((_, deadline)) = self.update {
+            if now >= deadline {
+                match self.worker.as_mut() {
+                    Some(worker) => worker.request(),
+                    None => self.widget.update(),
                 }
+                // widgets are allowed to change their minds about how
+                // often they want updating, so ask again every time
+                self.update = self
+                    .widget
+                    .update_frequency()
+                    .map(|f| (f, f.next_deadline(deadline)));
             }
         }
     }
+
+    fn click(&self, button: w::Button, x: f64, y: f64) {
+        // the widget drew itself inside its padding, so that's where
+        // it'll expect `x` to be measured from
+        self.widget
+            .on_click(button, x - f64::from(self.padding.0), y);
+        for (b, command) in self.actions.iter() {
+            if *b == button {
+                w::command::spawn(command);
+            }
+        }
+    }
+
+    fn draw(&self, d: &w::Drawing, loc: w::Located, limit: Option<i32>) -> i32 {
+        let (pad_left, pad_right) = self.padding;
+        let d = &w::Drawing {
+            limit: limit.map(|l| (l - pad_left - pad_right).max(0)),
+            overflow: self.overflow,
+            markup: self.markup,
+            ..*d
+        };
+        let inner = match loc {
+            w::Located::FromLeft(x) => w::Located::FromLeft(x + pad_left),
+            w::Located::FromRight(x) => w::Located::FromRight(x + pad_right),
+        };
+
+        // we don't know how big the background should be until the
+        // widget's been drawn, so draw it off to the side and then
+        // put it on top of the background afterwards
+        d.ctx.save();
+        if let Some((r, g, b)) = self.foreground {
+            d.ctx.set_source_rgb(r, g, b);
+        }
+        d.ctx.push_group();
+        let wd = self.widget.draw(d, inner) + pad_left + pad_right;
+        let drawn = d.ctx.pop_group();
+        if let Some((r, g, b)) = self.background {
+            d.ctx.set_source_rgb(r, g, b);
+            d.ctx
+                .rectangle(loc.target_x(d, wd), 0.0, wd as f64, d.size.ht as f64);
+            d.ctx.fill();
+        }
+        d.ctx.set_source(&drawn);
+        d.ctx.paint();
+        d.ctx.restore();
+
+        if self.is_stale() {
+            // underline the widget in red so it's clear that what
+            // it's showing might be out of date
+            let x = loc.target_x(d, wd);
+            d.ctx.save();
+            d.ctx.set_source_rgb(1.0, 0.0, 0.0);
+            d.ctx
+                .rectangle(x, d.size.ht as f64 - d.buffer / 2.0 - 2.0, wd as f64, 2.0);
+            d.ctx.fill();
+            d.ctx.restore();
+        }
+        wd
+    }
 }
 
 pub fn color_from_hex(input: &str) -> Result<(f64, f64, f64), failure::Error> {
@@ -67,79 +485,489 @@ pub fn color_from_hex(input: &str) -> Result<(f64, f64, f64), failure::Error> {
     }
 }
 
+/// A color, written in the config as a hex string
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+struct Color((f64, f64, f64));
+
+impl TryFrom<String> for Color {
+    type Error = failure::Error;
+
+    fn try_from(s: String) -> Result<Color, failure::Error> {
+        Ok(Color(color_from_hex(&s)?))
+    }
+}
+
+/// Everything at the top level of the config file
+#[derive(Deserialize)]
+struct TopLevel {
+    background: Option<Color>,
+    foreground: Option<Color>,
+    font: Option<String>,
+    position: Option<Position>,
+    // if this isn't given, then it comes from the font
+    height: Option<std::num::NonZeroU16>,
+    margin_x: Option<u16>,
+    margin_y: Option<u16>,
+    monitors: Option<Vec<OutputName>>,
+}
+
+/// A widget that's been read from the config but hasn't been set
+/// running yet
+enum Entry {
+    Sep,
+    Widget(Box<dyn w::Widget>, Box<WidgetOptions>),
+}
+
+/// Everything wrong with a config, plus anything that looks off but
+/// that we can live with
+#[derive(Default)]
+pub struct Report {
+    pub problems: Vec<String>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Report {
+    fn warn(&mut self, line: Option<usize>, message: String) {
+        self.warnings.push(Warning { line, message });
+    }
+}
+
+/// Something in the config that looks off, along with the line it's
+/// on (if we could find it) so that they can be listed in order
+pub struct Warning {
+    line: Option<usize>,
+    message: String,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Finds the lines that things in the config came from. This just
+/// looks at the text, so it won't find anything written with inline
+/// tables, but that's not how most people write these.
+struct Lines<'a> {
+    lines: Vec<&'a str>,
+    // every `[[...]]` header, as e.g. `widgets` or
+    // `outputs.DP-1.widgets`, along with the index of its line
+    headers: Vec<(String, usize)>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(body: &'a str) -> Lines<'a> {
+        let lines: Vec<&str> = body.lines().collect();
+        let headers = lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, l)| {
+                let l: String = l
+                    .chars()
+                    .filter(|c| !c.is_whitespace() && *c != '"' && *c != '\'')
+                    .collect();
+                let path = l.strip_prefix("[[")?.split("]]").next()?;
+                Some((path.to_string(), i))
+            })
+            .collect();
+        Lines { lines, headers }
+    }
+
+    /// The line where `key` is set, looking from line `from` up until
+    /// the next section header
+    fn find_key(&self, from: usize, key: &str) -> Option<usize> {
+        for (i, line) in self.lines.iter().enumerate().skip(from) {
+            let line = line.trim_start();
+            if line.starts_with('[') {
+                return None;
+            }
+            let lhs = line.split('=').next().unwrap_or("").trim();
+            if line.contains('=') && lhs.trim_matches(|c| c == '"' || c == '\'') == key {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    /// The line that widget `index` in the array at `path` starts
+    /// on, or where `key` is set in it if that can be found
+    fn widget_line(&self, path: &str, index: usize, key: Option<&str>) -> Option<usize> {
+        let header = self
+            .headers
+            .iter()
+            .filter(|(p, _)| p == path)
+            .nth(index)
+            .map(|(_, line)| *line)?;
+        Some(
+            key.and_then(|k| self.find_key(header + 1, k))
+                .unwrap_or(header),
+        )
+    }
+
+    /// Describe where widget `index` in the array at `path` (and
+    /// optionally one of its keys) lives in the config, as a prefix for
+    /// messages about it
+    fn widget(&self, path: &str, index: usize, name: Option<&str>, key: Option<&str>) -> String {
+        let mut out = format!("[[{}]] #{}", path, index + 1);
+        if let Some(name) = name {
+            out.push_str(&format!(" ({})", name));
+        }
+        if let Some(line) = self.widget_line(path, index, key) {
+            out.push_str(&format!(", line {}", line + 1));
+        }
+        out
+    }
+
+    /// The line where `key` is set in the `[table]` at `path`, or the
+    /// table's header if `key` isn't in it
+    fn table_line(&self, path: &str, key: &str) -> Option<usize> {
+        let header = self.lines.iter().position(|l| {
+            let l: String = l
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != '"' && *c != '\'')
+                .collect();
+            l.strip_prefix('[').and_then(|l| l.split(']').next()) == Some(path)
+        })?;
+        Some(self.find_key(header + 1, key).unwrap_or(header))
+    }
+
+    fn top_level(&self, key: &str) -> String {
+        match self.find_key(0, key) {
+            Some(line) => format!("line {}", line + 1),
+            None => "top level".to_string(),
+        }
+    }
+}
+
+/// Read through the config, setting up all the widgets in it but not
+/// starting any of them. This keeps going after the first problem so
+/// that `--check` can report as much as possible at once. The layout
+/// that every monitor gets by default comes first, followed by any
+/// that are just for one monitor.
+#[allow(clippy::type_complexity)]
+fn parse(
+    body: &str,
+) -> (
+    Option<TopLevel>,
+    Vec<(Option<OutputName>, Vec<Entry>)>,
+    Report,
+) {
+    let lines = Lines::new(body);
+    let mut report = Report::default();
+
+    let mut table = match body.parse::<toml::Value>() {
+        Ok(toml::Value::Table(table)) => table,
+        Ok(_) => unreachable!("a TOML document is always a table"),
+        Err(err) => {
+            // this already says which line it was on
+            report.problems.push(err.to_string());
+            return (None, Vec::new(), report);
+        }
+    };
+
+    // we look at the widgets one at a time, so that we can say which
+    // one something was wrong with
+    let widgets = match table.remove("widgets") {
+        Some(toml::Value::Array(widgets)) => widgets,
+        Some(other) => {
+            report.problems.push(format!(
+                "{}: `widgets` should be an array of tables, found {}",
+                lines.top_level("widgets"),
+                other.type_str()
+            ));
+            Vec::new()
+        }
+        None => {
+            report
+                .problems
+                .push("there aren't any `[[widgets]]` sections".to_string());
+            Vec::new()
+        }
+    };
+    let mut layouts = vec![(
+        None,
+        parse_widgets(&lines, "widgets", &widgets, &mut report),
+    )];
+
+    // each of these is a whole other set of widgets, for the monitor
+    // it's named after
+    match table.remove("outputs") {
+        Some(toml::Value::Table(outputs)) => {
+            for (key, output) in outputs.iter() {
+                let path = format!("outputs.{}.widgets", key);
+                let mut output = match output {
+                    toml::Value::Table(output) => output.clone(),
+                    other => {
+                        report.problems.push(format!(
+                            "{}: `outputs.{}` should be a table, found {}",
+                            lines.top_level("outputs"),
+                            key,
+                            other.type_str()
+                        ));
+                        continue;
+                    }
+                };
+                let widgets = match output.remove("widgets") {
+                    Some(toml::Value::Array(widgets)) => widgets,
+                    Some(other) => {
+                        report.problems.push(format!(
+                            "`outputs.{}.widgets` should be an array of tables, found {}",
+                            key,
+                            other.type_str()
+                        ));
+                        continue;
+                    }
+                    None => {
+                        report
+                            .problems
+                            .push(format!("there aren't any `[[{}]]` sections", path));
+                        continue;
+                    }
+                };
+                for unknown in output.keys() {
+                    report.warn(
+                        lines.table_line(&format!("outputs.{}", key), unknown),
+                        format!("`outputs.{}`: unknown key `{}`", key, unknown),
+                    );
+                }
+                let entries = parse_widgets(&lines, &path, &widgets, &mut report);
+                layouts.push((Some(OutputName::from_key(key)), entries));
+            }
+        }
+        Some(other) => report.problems.push(format!(
+            "{}: `outputs` should be a table, found {}",
+            lines.top_level("outputs"),
+            other.type_str()
+        )),
+        None => (),
+    }
+
+    let mut rest = w::Section::new(&table);
+    let top = match rest.parse::<TopLevel>() {
+        Ok(top) => Some(top),
+        Err(err) => {
+            report
+                .problems
+                .push(match err.downcast_ref::<w::KeyError>() {
+                    Some(err @ w::KeyError { key: Some(key), .. }) => {
+                        format!("{}: {}", lines.top_level(key), err)
+                    }
+                    _ => err.to_string(),
+                });
+            None
+        }
+    };
+    for key in rest.unused.iter() {
+        report.warn(
+            lines.find_key(0, key),
+            format!("{}: unknown key `{}`", lines.top_level(key), key),
+        );
+    }
+
+    // these got found a section at a time, but they're easier to
+    // follow in the order they come in the file
+    report
+        .warnings
+        .sort_by_key(|warning| warning.line.unwrap_or(usize::MAX));
+    (top, layouts, report)
+}
+
+/// Set up the widgets in one `[[...widgets]]` array, where `path` is
+/// what the array's headers look like
+fn parse_widgets(
+    lines: &Lines,
+    path: &str,
+    widgets: &[toml::Value],
+    report: &mut Report,
+) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut seps = 0;
+
+    for (i, section) in widgets.iter().enumerate() {
+        let section = match section.as_table() {
+            Some(section) => section,
+            None => {
+                report.problems.push(format!(
+                    "{}: should be a table, found {}",
+                    lines.widget(path, i, None, None),
+                    section.type_str()
+                ));
+                continue;
+            }
+        };
+
+        let mut common = w::Section::new(section);
+        let options: WidgetOptions = match common.parse() {
+            Ok(options) => options,
+            Err(err) => {
+                let name = section.get("name").and_then(|n| n.as_str());
+                report.problems.push(describe(lines, path, i, name, err));
+                continue;
+            }
+        };
+        if options.name == "sep" {
+            seps += 1;
+            if seps == 3 {
+                report.problems.push(format!(
+                    "{}: there can only be two `sep`s in `[[{}]]`",
+                    lines.widget(path, i, Some("sep"), Some("name")),
+                    path
+                ));
+            }
+            entries.push(Entry::Sep);
+            continue;
+        }
+
+        let mut specific = w::Section::new(section);
+        let widget = w::mk_widget(&options.name, &mut specific);
+        // a key's only unknown if neither the widget nor the settings
+        // shared by all widgets used it. This is worth knowing even if
+        // the widget didn't work out, since a misspelled key might be
+        // why.
+        for key in specific.unused.iter().filter(|k| common.unused.contains(k)) {
+            report.warn(
+                lines.widget_line(path, i, Some(key)),
+                format!(
+                    "{}: unknown key `{}`",
+                    lines.widget(path, i, Some(&options.name), Some(key)),
+                    key
+                ),
+            );
+        }
+        match widget {
+            Ok(widget) => entries.push(Entry::Widget(widget, Box::new(options))),
+            Err(err) => {
+                report
+                    .problems
+                    .push(describe(lines, path, i, Some(&options.name), err));
+            }
+        }
+    }
+    entries
+}
+
+/// Turn an error from setting up a widget into something that says
+/// where in the config it came from
+fn describe(
+    lines: &Lines,
+    path: &str,
+    index: usize,
+    name: Option<&str>,
+    err: failure::Error,
+) -> String {
+    match err.downcast_ref::<w::KeyError>() {
+        Some(key_err) => format!(
+            "{}: {}",
+            lines.widget(path, index, name, key_err.key.as_deref()),
+            key_err
+        ),
+        None => format!("{}: {}", lines.widget(path, index, name, None), err),
+    }
+}
+
+/// Go over a config without actually starting anything up, and
+/// report everything wrong with it
+pub fn check(body: &str) -> Report {
+    let (_, _, report) = parse(body);
+    report
+}
+
 impl Config {
-    pub fn from_toml(input: toml::Value) -> Result<Config, failure::Error> {
+    pub fn from_str(body: &str) -> Result<Config, failure::Error> {
+        let (top, layouts, report) = parse(body);
+        for warning in report.warnings.iter() {
+            eprintln!("warning: {}", warning);
+        }
+        if let Some(problem) = report.problems.first() {
+            match report.problems.len() {
+                1 => bail!("{}", problem),
+                n => bail!("{} (and {} more problems)", problem, n - 1),
+            }
+        }
+        // there's always a top level if there weren't any problems
+        let top = top.ok_or_else(|| format_err!("invalid config"))?;
+
+        let waker = Arc::new(Waker::new()?);
+        let bars = layouts
+            .into_iter()
+            .map(|(output, entries)| Bar::new(output, entries, &waker))
+            .collect();
         let mut conf = Config {
-            left: Vec::new(),
-            right: Vec::new(),
-            bg_color: defaults::BG_COLOR,
-            fg_color: defaults::FG_COLOR,
-            font: format!("{} {}", defaults::FONT_FAMILY, defaults::FONT_SIZE),
+            bars,
+            monitors: top.monitors,
+            bg_color: top.background.map_or(defaults::BG_COLOR, |c| c.0),
+            fg_color: top.foreground.map_or(defaults::FG_COLOR, |c| c.0),
+            font: top
+                .font
+                .unwrap_or_else(|| format!("{} {}", defaults::FONT_FAMILY, defaults::FONT_SIZE)),
             height: 0,
             buffer: 0,
+            position: top.position.unwrap_or(Position::Top),
+            margin_x: top.margin_x.map_or(0, i32::from),
+            margin_y: top.margin_y.map_or(0, i32::from),
+            waker,
+            flash: None,
+            scrolling: Cell::new(false),
         };
-        let table = input
-            .as_table()
-            .ok_or_else(|| format_err!("invalid config"))?;
-        let widgets = &table["widgets"];
-        let mut target = &mut conf.left;
-        for section in widgets
-            .as_array()
-            .ok_or_else(|| format_err!("invalid config"))?
-        {
-            let section = section
-                .as_table()
-                .ok_or_else(|| format_err!("invalid config"))?;
-            let name = section["name"]
-                .as_str()
-                .ok_or_else(|| format_err!("invalid config"))?;
-            if name == "sep" {
-                target = &mut conf.right;
-            } else {
-                target.push(WidgetWrapper::new(w::mk_widget(name, section)?));
-            }
-        }
-
-        if let Some(color) = table.get("background") {
-            conf.bg_color = color_from_hex(
-                color
-                    .as_str()
-                    .ok_or_else(|| format_err!("`background` not a str"))?,
-            )?;
-        }
-        if let Some(color) = table.get("foreground") {
-            conf.fg_color = color_from_hex(
-                color
-                    .as_str()
-                    .ok_or_else(|| format_err!("`foreground` not a str"))?,
-            )?;
-        }
-        if let Some(font) = table.get("font") {
-            conf.font = font
-                .as_str()
-                .ok_or_else(|| format_err!("`font` not a str"))?
-                .to_string();
-        }
-        conf.right.reverse();
 
         let text_height = conf.calc_text_height();
-        let buffer = text_height / 4;
-        conf.height = conf.calc_text_height() + buffer * 2;
-        conf.buffer = buffer;
+        match top.height {
+            // the text stays in the middle of a bar that's been given
+            // its own height, even if it doesn't fit
+            Some(height) => {
+                conf.height = i32::from(height.get());
+                conf.buffer = (conf.height - text_height) / 2;
+            }
+            None => {
+                conf.buffer = text_height / 4;
+                conf.height = text_height + conf.buffer * 2;
+            }
+        }
         Ok(conf)
     }
 
     pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Config, failure::Error> {
         let body = std::fs::read_to_string(path)?;
-        let val = body.parse::<toml::Value>()?;
-        Config::from_toml(val)
+        Config::from_str(&body)
     }
 
-    pub fn find_config() -> Result<Config, failure::Error> {
-        if let Some(p) = xdg::BaseDirectories::new()?.find_config_file("knurling/knurling.toml") {
-            return Config::from_file(p);
+    /// Load the config from `path` if there is one, or the built-in
+    /// default otherwise
+    pub fn load(path: Option<&std::path::Path>) -> Result<Config, failure::Error> {
+        match path {
+            Some(path) => Config::from_file(path)
+                .map_err(|err| format_err!("Unable to load {}: {}", path.display(), err)),
+            None => Config::from_str(DEFAULT_CONFIG),
+        }
+    }
+
+    /// Where the user's config lives, if they have one
+    pub fn find_config_path() -> Result<Option<std::path::PathBuf>, failure::Error> {
+        Ok(xdg::BaseDirectories::new()?.find_config_file("knurling/knurling.toml"))
+    }
+
+    /// Where the user's config would go if they made one. This makes
+    /// the directory for it if it isn't there, so that we can watch
+    /// for the file turning up.
+    pub fn default_config_path() -> Result<std::path::PathBuf, failure::Error> {
+        Ok(xdg::BaseDirectories::new()?.place_config_file("knurling/knurling.toml")?)
+    }
+
+    /// Show an error message at the start of the bar for a little
+    /// while
+    pub fn flash_error(&mut self, msg: &str) {
+        // the bar is only one line tall, so don't let a multi-line
+        // error spill out of it
+        let msg = msg.lines().collect::<Vec<_>>().join(" ");
+        self.flash = Some((msg, time::Instant::now() + defaults::FLASH_DURATION));
+    }
+
+    fn current_flash(&self) -> Option<&str> {
+        match self.flash {
+            Some((ref msg, until)) if time::Instant::now() < until => Some(msg),
+            _ => None,
         }
-        Err(format_err!("Unable to find `knurling.toml`"))
     }
 
     pub fn draw(
@@ -148,7 +976,10 @@ impl Config {
         layout: &pango::Layout,
         stdin: &str,
         size: w::Size,
-    ) -> Result<(), failure::Error> {
+        bar_index: usize,
+    ) -> Result<Vec<Extent>, failure::Error> {
+        let bar = &self.bars[bar_index];
+
         // paint the background
         {
             let (r, g, b) = self.bg_color;
@@ -165,33 +996,323 @@ impl Config {
         // set up a struct with everything that widgets need to draw
         let d = w::Drawing {
             ctx,
-            lyt: &layout,
+            lyt: layout,
             size,
             stdin,
             buffer: self.buffer as f64,
+            limit: None,
+            overflow: w::Overflow::End,
+            markup: false,
         };
 
-        let mut offset = 10;
-        for w in self.left.iter() {
-            offset += 10 + w.widget.draw(&d, w::Located::FromLeft(offset));
+        // work out how wide everything wants to be, by drawing it
+        // all to a group that we then throw away
+        let measure = |widgets: &[WidgetWrapper]| -> Vec<i32> {
+            widgets
+                .iter()
+                .map(|wr| {
+                    ctx.push_group();
+                    let wd = wr.draw(&d, w::Located::FromLeft(0), None);
+                    ctx.pop_group();
+                    wd
+                })
+                .collect()
+        };
+        // ...but anything with a `max_width` isn't getting more than
+        // that, no matter how much it wants
+        let capped = |widgets: &[WidgetWrapper], natural: &[i32]| -> Vec<i32> {
+            widgets
+                .iter()
+                .zip(natural.iter())
+                .map(|(wr, wd)| match wr.max_width {
+                    Some(max) => (*wd).min(max.pixels(layout)),
+                    None => *wd,
+                })
+                .collect()
+        };
+        // neighbouring widgets get as big a gap as either of them
+        // asks for
+        let gaps = |widgets: &[WidgetWrapper]| -> Vec<i32> {
+            widgets
+                .windows(2)
+                .map(|pair| pair[0].margin.max(pair[1].margin))
+                .collect()
+        };
+        let left_gaps = gaps(&bar.left);
+        let center_gaps = gaps(&bar.center);
+        let right_gaps = gaps(&bar.right);
+        let sum = |ns: &[i32]| -> i32 { ns.iter().sum() };
+        let total = |widths: &[i32], gaps: &[i32]| -> i32 { sum(widths) + sum(gaps) };
+        let left_natural = measure(&bar.left);
+        let center_natural = measure(&bar.center);
+        let right_natural = measure(&bar.right);
+        let mut left_wds = capped(&bar.left, &left_natural);
+        let mut center_wds = capped(&bar.center, &center_natural);
+        let mut right_wds = capped(&bar.right, &right_natural);
+
+        // if there's an error to show, then it goes before everything
+        // else and pushes the left-hand widgets over
+        let flash = self.current_flash().map(|msg| {
+            ctx.push_group();
+            let wd = w::Located::FromLeft(0).draw_text(&d, msg);
+            ctx.pop_group();
+            (msg, wd)
+        });
+        let left_x = SPACING + flash.map_or(0, |(_, wd)| wd + SPACING);
+
+        // the right-hand side gets all the room it wants, as long as
+        // it fits in the bar at all...
+        fit(&mut right_wds, sum(&right_gaps), size.wd - 2 * SPACING);
+        let right_x = if bar.right.is_empty() {
+            size.wd
+        } else {
+            size.wd - SPACING - total(&right_wds, &right_gaps)
+        };
+        // ...and the center goes in the middle if there's room, but
+        // gets pushed over to keep clear of the right-hand side...
+        if !bar.center.is_empty() {
+            fit(
+                &mut center_wds,
+                sum(&center_gaps),
+                right_x - SPACING - left_x,
+            );
         }
-        offset = 10;
-        for w in self.right.iter() {
-            offset += 10 + w.widget.draw(&d, w::Located::FromRight(offset));
+        let center_wd = total(&center_wds, &center_gaps);
+        let center_x = ((size.wd - center_wd) / 2)
+            .min(right_x - SPACING - center_wd)
+            .max(left_x);
+        // ...and the left-hand side gets whatever's left
+        let left_limit = if bar.center.is_empty() {
+            right_x
+        } else {
+            center_x
+        } - SPACING;
+        fit(&mut left_wds, sum(&left_gaps), left_limit - left_x);
+
+        let mut extents = Vec::new();
+        let sections = [
+            (
+                Section::Left,
+                &bar.left,
+                &left_natural,
+                &left_wds,
+                &left_gaps,
+                left_x,
+                left_limit,
+            ),
+            (
+                Section::Center,
+                &bar.center,
+                &center_natural,
+                &center_wds,
+                &center_gaps,
+                center_x,
+                size.wd,
+            ),
+            (
+                Section::Right,
+                &bar.right,
+                &right_natural,
+                &right_wds,
+                &right_gaps,
+                right_x,
+                size.wd,
+            ),
+        ];
+        for (section, widgets, natural, wds, gaps, start, limit) in sections.iter() {
+            // anything that can't shrink to fit gets cut off, rather
+            // than drawing over its neighbours
+            ctx.save();
+            ctx.rectangle(0.0, 0.0, *limit as f64, size.ht as f64);
+            ctx.clip();
+            if let (Section::Left, Some((msg, _))) = (section, flash) {
+                let (r, g, b) = defaults::ERROR_COLOR;
+                ctx.set_source_rgb(r, g, b);
+                w::Located::FromLeft(SPACING).draw_text(&d, msg);
+                let (r, g, b) = self.fg_color;
+                ctx.set_source_rgb(r, g, b);
+            }
+            let mut x = *start;
+            for (index, wr) in widgets.iter().enumerate() {
+                let (wd, natural) = (wds[index], natural[index]);
+                if x >= *limit {
+                    break;
+                }
+                let shrunk = if wd < natural { Some(wd) } else { None };
+                if shrunk.is_some() && wr.overflow == w::Overflow::Scroll {
+                    self.scrolling.set(true);
+                }
+                // things on the right stay flush against the end of
+                // the bar, even if they've changed size since we
+                // measured them
+                let loc = if *section == Section::Right {
+                    w::Located::FromRight(size.wd - x - wd)
+                } else {
+                    w::Located::FromLeft(x)
+                };
+                let drawn_wd = wr.draw(&d, loc, shrunk);
+                extents.push(Extent {
+                    bar: bar_index,
+                    section: *section,
+                    index,
+                    x: loc.target_x(&d, drawn_wd),
+                    wd: drawn_wd.min(limit - x) as f64,
+                });
+                x += wd + gaps.get(index).unwrap_or(&0);
+            }
+            ctx.restore();
         }
 
-        Ok(())
+        Ok(extents)
+    }
+
+    /// Pass a click along to whichever widget was drawn at that spot
+    pub fn click(&self, extents: &[Extent], button: w::Button, x: f64, y: f64) {
+        for e in extents.iter() {
+            if x >= e.x && x < e.x + e.wd {
+                let section = self.bars.get(e.bar).map(|bar| bar.section(e.section));
+                if let Some(wr) = section.and_then(|s| s.get(e.index)) {
+                    wr.click(button, x - e.x, y);
+                }
+                return;
+            }
+        }
     }
 
     pub fn update(&mut self) {
-        for w in self.left.iter_mut() {
-            w.update()
+        // we'll find out whether anything's still scrolling when we
+        // next draw
+        self.scrolling.set(false);
+        let now = time::Instant::now();
+        for w in self.bars.iter_mut().flat_map(|bar| bar.widgets_mut()) {
+            w.update(now)
+        }
+    }
+
+    /// The soonest that any widget wants to be updated, if any of
+    /// them do
+    pub fn next_deadline(&self) -> Option<time::Instant> {
+        // if we're showing an error, we need to wake up to stop
+        // showing it
+        let flash_end = self
+            .flash
+            .as_ref()
+            .map(|(_, until)| *until)
+            .filter(|until| *until > time::Instant::now());
+        let scroll = if self.scrolling.get() {
+            Some(time::Instant::now() + SCROLL_TICK)
+        } else {
+            None
+        };
+        self.widgets()
+            .filter_map(|w| w.deadline())
+            .chain(flash_end)
+            .chain(scroll)
+            .min()
+    }
+
+    /// Change the text of the widget with the given `id`
+    pub fn set_text(&self, id: &str, text: &str) -> Result<(), failure::Error> {
+        let wr = self
+            .widgets()
+            .find(|wr| wr.id.as_deref() == Some(id))
+            .ok_or_else(|| format_err!("no widget with id {:?}", id))?;
+        wr.widget.set_text(text)
+    }
+
+    /// A human-readable summary of all the widgets, mostly useful for
+    /// debugging
+    pub fn dump_state(&self) -> String {
+        let mut out = String::new();
+        for bar in self.bars.iter() {
+            // the default bar's widgets are just `left`, `center` and
+            // `right`, but the others say which monitor they're for
+            let prefix = match bar.output {
+                None => String::new(),
+                Some(OutputName::Primary) => "primary/".to_string(),
+                Some(OutputName::Index(i)) => format!("{}/", i),
+                Some(OutputName::Name(ref name)) => format!("{}/", name),
+            };
+            let sections = [
+                ("left", &bar.left),
+                ("center", &bar.center),
+                ("right", &bar.right),
+            ];
+            for (section, widgets) in sections.iter() {
+                for wr in widgets.iter() {
+                    out.push_str(&format!(
+                        "{}{} {} id={} stale={}\n",
+                        prefix,
+                        section,
+                        wr.name,
+                        wr.id.as_deref().unwrap_or("-"),
+                        wr.is_stale()
+                    ));
+                }
+            }
+        }
+        out
+    }
+
+    /// All the file descriptors that widgets want us to wait on,
+    /// including the one that tells us a background update finished
+    pub fn fds(&self) -> Vec<std::os::unix::io::RawFd> {
+        let mut fds: Vec<_> = self.widgets().filter_map(|w| w.widget.fd()).collect();
+        fds.push(self.waker.fd());
+        fds
+    }
+
+    /// Let whichever widget owns this file descriptor know that it's
+    /// got something for them
+    pub fn handle_readable(&mut self, fd: std::os::unix::io::RawFd) {
+        if fd == self.waker.fd() {
+            // there's nothing to do here but redraw, which the main
+            // loop is about to do anyway
+            self.waker.drain();
+            return;
+        }
+        for w in self.widgets() {
+            if w.widget.fd() == Some(fd) {
+                w.widget.on_readable();
+            }
         }
-        for w in self.right.iter_mut() {
-            w.update()
+    }
+
+    /// Every widget on every bar
+    fn widgets(&self) -> impl Iterator<Item = &WidgetWrapper> {
+        self.bars.iter().flat_map(|bar| bar.widgets())
+    }
+
+    /// Whether the `index`th monitor should get a bar
+    pub fn shows_on(&self, index: usize, monitor: &Monitor) -> bool {
+        match self.monitors {
+            Some(ref names) => names.iter().any(|n| n.matches(index, monitor)),
+            None => true,
         }
     }
 
+    /// Which bar goes on the `index`th monitor. Naming a monitor's
+    /// output is more specific than giving its number, which is more
+    /// specific than saying it's the primary one, so that's the order
+    /// we look for them in.
+    pub fn bar_for(&self, index: usize, monitor: &Monitor) -> usize {
+        let kinds: [fn(&OutputName) -> bool; 3] = [
+            |n| matches!(n, OutputName::Name(_)),
+            |n| matches!(n, OutputName::Index(_)),
+            |n| matches!(n, OutputName::Primary),
+        ];
+        kinds
+            .iter()
+            .find_map(|kind| {
+                self.bars.iter().position(|bar| {
+                    bar.output
+                        .as_ref()
+                        .is_some_and(|n| kind(n) && n.matches(index, monitor))
+                })
+            })
+            .unwrap_or(0)
+    }
+
     pub fn font(&self) -> &str {
         &self.font
     }
@@ -200,6 +1321,16 @@ impl Config {
         self.height
     }
 
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// How far the bar should be from the sides of the screen, and
+    /// from the edge it's on
+    pub fn margins(&self) -> (i32, i32) {
+        (self.margin_x, self.margin_y)
+    }
+
     fn calc_text_height(&self) -> i32 {
         use pango::LayoutExt;
 
@@ -217,3 +1348,341 @@ impl Config {
         h / pango::SCALE
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    type Clicks = Arc<Mutex<Vec<(&'static str, w::Button, f64)>>>;
+
+    /// A widget that's always the same width, and remembers where it
+    /// got clicked
+    struct Fixed {
+        name: &'static str,
+        wd: i32,
+        clicks: Clicks,
+    }
+
+    impl w::Widget for Fixed {
+        fn draw(&self, _: &w::Drawing, _: w::Located) -> i32 {
+            self.wd
+        }
+
+        fn on_click(&self, button: w::Button, x: f64, _: f64) {
+            self.clicks.lock().unwrap().push((self.name, button, x));
+        }
+    }
+
+    type Widths<'a> = &'a [(&'static str, i32)];
+
+    /// A config with `left`, `center` and `right` widgets of the given
+    /// widths, all with the same extra `options`, laid out on a bar
+    /// 1000 pixels wide
+    fn laid_out(
+        left: Widths,
+        center: Widths,
+        right: Widths,
+        options: &str,
+    ) -> (Config, Vec<Extent>, Clicks) {
+        let clicks = Clicks::default();
+        let mut config = Config::from_str("[[widgets]]\nname = \"stdin\"\n").unwrap();
+        let waker = config.waker.clone();
+        let fixed = |&(name, wd): &(&'static str, i32)| {
+            let widget = Box::new(Fixed {
+                name,
+                wd,
+                clicks: clicks.clone(),
+            });
+            let options = toml::from_str(&format!("name = \"fixed\"\n{}", options)).unwrap();
+            WidgetWrapper::new(widget, options, &waker)
+        };
+        config.bars = vec![Bar {
+            output: None,
+            left: left.iter().map(fixed).collect(),
+            center: center.iter().map(fixed).collect(),
+            right: right.iter().map(fixed).collect(),
+        }];
+
+        let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, 1000, 30).unwrap();
+        let ctx = cairo::Context::new(&surface);
+        let layout = pangocairo::functions::create_layout(&ctx).unwrap();
+        let size = w::Size {
+            wd: 1000,
+            ht: 30,
+            xo: 0,
+            yo: 0,
+        };
+        let extents = config.draw(&ctx, &layout, "", size, 0).unwrap();
+        (config, extents, clicks)
+    }
+
+    /// Click at each of `xs`, and see who got clicked where
+    fn click_at(
+        left: Widths,
+        center: Widths,
+        right: Widths,
+        options: &str,
+        xs: &[f64],
+    ) -> Vec<(&'static str, f64)> {
+        let (config, extents, clicks) = laid_out(left, center, right, options);
+        for x in xs {
+            config.click(&extents, w::Button::Left, *x, 5.0);
+        }
+        let clicks = clicks.lock().unwrap();
+        clicks.iter().map(|(name, _, x)| (*name, *x)).collect()
+    }
+
+    #[test]
+    fn clicks_go_to_the_widget_underneath() {
+        // the left ones start 10 pixels in with 10 pixels between
+        // them, the right ones end 10 pixels from the other end, and
+        // the center ones go in the middle
+        let clicks = click_at(
+            &[("a", 30), ("b", 50)],
+            &[("e", 100)],
+            &[("c", 40), ("d", 20)],
+            "",
+            &[
+                9.0, 10.0, 39.0, 40.0, 50.0, 99.0, 449.0, 450.0, 549.0, 550.0, 919.0, 920.0, 959.0,
+                975.0, 990.0,
+            ],
+        );
+        assert_eq!(
+            clicks,
+            vec![
+                ("a", 0.0),
+                ("a", 29.0),
+                ("b", 0.0),
+                ("b", 49.0),
+                ("e", 0.0),
+                ("e", 99.0),
+                ("c", 0.0),
+                ("c", 39.0),
+                ("d", 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn clicks_on_the_left_stop_at_the_center() {
+        // the left side gets cut off 10 pixels short of the center,
+        // and whatever got cut off can't be clicked on
+        let clicks = click_at(
+            &[("a", 30), ("b", 500)],
+            &[("e", 100)],
+            &[("c", 40)],
+            "",
+            &[439.0, 445.0, 450.0],
+        );
+        assert_eq!(clicks, vec![("b", 389.0), ("e", 0.0)]);
+    }
+
+    #[test]
+    fn clicks_on_padding_and_margins() {
+        // the padding is part of the widget, so clicking on it counts,
+        // but the margins between widgets belong to nobody. The widget
+        // gets told where the click was from where it drew itself,
+        // which is inside the padding.
+        let clicks = click_at(
+            &[("a", 30), ("b", 50)],
+            &[],
+            &[],
+            "padding_left = 5\npadding_right = 5\nmargin = 20\n",
+            &[9.0, 10.0, 15.0, 49.0, 50.0, 69.0, 70.0, 129.0, 130.0],
+        );
+        assert_eq!(
+            clicks,
+            vec![
+                ("a", -5.0),
+                ("a", 0.0),
+                ("a", 34.0),
+                ("b", -5.0),
+                ("b", 54.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn fitting_widgets_in() {
+        // there's already enough room, so nothing changes
+        let mut widths = [100, 50];
+        fit(&mut widths, 10, 200);
+        assert_eq!(widths, [100, 50]);
+
+        // the widest one gives up room first...
+        let mut widths = [300, 50, 40];
+        fit(&mut widths, 10, 200);
+        assert_eq!(widths, [100, 50, 40]);
+
+        // ...and if all of its room isn't enough, the next widest
+        // one gives up some too
+        let mut widths = [100, 90, 10];
+        fit(&mut widths, 0, 50);
+        assert_eq!(widths, [0, 40, 10]);
+
+        // if even the gaps don't fit, everything goes down to nothing
+        // rather than below it
+        let mut widths = [30, 20];
+        fit(&mut widths, 100, 50);
+        assert_eq!(widths, [0, 0]);
+    }
+
+    fn monitor(name: &str, primary: bool) -> Monitor {
+        Monitor {
+            name: Some(name.to_string()),
+            primary,
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        }
+    }
+
+    #[test]
+    fn picking_monitors() {
+        let config =
+            Config::from_str("monitors = [\"primary\", \"2\"]\n\n[[widgets]]\nname = \"time\"\n")
+                .unwrap();
+        let (laptop, left, right) = (
+            monitor("eDP-1", true),
+            monitor("DP-1", false),
+            monitor("DP-2", false),
+        );
+        assert!(config.shows_on(0, &laptop));
+        assert!(!config.shows_on(1, &left));
+        assert!(config.shows_on(2, &right));
+
+        // without `monitors`, everything gets a bar
+        let config = Config::from_str("[[widgets]]\nname = \"time\"\n").unwrap();
+        assert!(config.shows_on(1, &left));
+    }
+
+    #[test]
+    fn picking_bars() {
+        let config = Config::from_str(
+            "[[widgets]]\nname = \"time\"\n\n\
+             [[outputs.primary.widgets]]\nname = \"text\"\n\n\
+             [[outputs.1.widgets]]\nname = \"text\"\n\n\
+             [[outputs.DP-2.widgets]]\nname = \"text\"\n",
+        )
+        .unwrap();
+        let bar = |index, monitor: &Monitor| {
+            let bar = config.bar_for(index, monitor);
+            config.bars[bar].output.as_ref().map(|name| match name {
+                OutputName::Primary => "primary".to_string(),
+                OutputName::Index(i) => i.to_string(),
+                OutputName::Name(name) => name.clone(),
+            })
+        };
+        // nothing's just for this one, so it gets the default bar
+        assert_eq!(bar(3, &monitor("HDMI-1", false)), None);
+        assert_eq!(bar(0, &monitor("eDP-1", true)).as_deref(), Some("primary"));
+        // a number beats being primary...
+        assert_eq!(bar(1, &monitor("DP-1", true)).as_deref(), Some("1"));
+        // ...and a name beats both
+        assert_eq!(bar(1, &monitor("DP-2", true)).as_deref(), Some("DP-2"));
+    }
+
+    #[test]
+    fn only_blocking_widgets_get_workers() {
+        let config = Config::from_str(
+            "[[widgets]]\nname = \"time\"\n\n[[widgets]]\nname = \"cpu\"\n\n[[widgets]]\nname = \"text\"\n",
+        )
+        .unwrap();
+        let widgets: Vec<_> = config.widgets().collect();
+        // the clock only needs redrawing on time, and the text never
+        // updates at all
+        assert!(widgets[0].update.is_some());
+        assert!(widgets[0].worker.is_none());
+        assert!(widgets[1].worker.is_some());
+        assert!(widgets[2].update.is_none());
+        assert!(widgets[2].worker.is_none());
+    }
+
+    /// Where `--check` says each problem is
+    fn problem_lines(body: &str) -> Vec<String> {
+        check(body)
+            .problems
+            .iter()
+            .map(|p| p.split(':').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn problems_point_at_their_keys() {
+        let lines = problem_lines(
+            "[[widgets]]\n\
+             name = \"memory\"\n\
+             format = \"{nope}\"\n\
+             \n\
+             [[widgets]]\n\
+             name = \"time\"\n\
+             locale = \"xx_YY\"\n\
+             \n\
+             [[widgets]]\n\
+             interval = 5\n\
+             name = \"clock\"\n\
+             \n\
+             [[widgets]]\n\
+             name = \"sep\"\n\
+             [[widgets]]\n\
+             name = \"sep\"\n\
+             [[widgets]]\n\
+             name = \"sep\"\n",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "[[widgets]] #1 (memory), line 3",
+                "[[widgets]] #2 (time), line 7",
+                "[[widgets]] #3 (clock), line 11",
+                "[[widgets]] #6 (sep), line 18",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_fields() {
+        // there's no line for a key that isn't there, so this points
+        // at the section it should have been in
+        let report = check(
+            "[[widgets]]\nname = \"text\"\n\n[[widgets]]\nname = \"mpd\"\nhost = \"localhost\"\n",
+        );
+        assert_eq!(
+            report.problems,
+            vec!["[[widgets]] #2 (mpd), line 4: missing field `port`"]
+        );
+    }
+
+    #[test]
+    fn warnings_in_order() {
+        let report = check(
+            "[[widgets]]\n\
+             name = \"mpd\"\n\
+             colour = \"red\"\n\
+             \n\
+             [[outputs.DP-1.widgets]]\n\
+             name = \"time\"\n\
+             \n\
+             [outputs.DP-1]\n\
+             extra = 1\n\
+             \n\
+             [[widgets]]\n\
+             name = \"time\"\n\
+             frobnicate = 2\n",
+        );
+        // the mpd widget's broken, but it's still worth hearing that
+        // one of its keys got ignored
+        assert_eq!(report.problems.len(), 1);
+        let warnings: Vec<_> = report.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "[[widgets]] #1 (mpd), line 3: unknown key `colour`",
+                "`outputs.DP-1`: unknown key `extra`",
+                "[[widgets]] #2 (time), line 13: unknown key `frobnicate`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// Where the socket goes, in `$XDG_RUNTIME_DIR/knurling`. A bar with
+/// a name gets `knurling-<name>.sock` instead, so that more than one
+/// bar can be running at once.
+const SOCKET_NAME: &str = "knurling.sock";
+
+/// The things you can ask a running bar to do over its socket
+#[derive(Debug)]
+pub enum Request {
+    Set { id: String, text: String },
+    Reload,
+    Hide,
+    Show,
+    Toggle,
+    Quit,
+    DumpState,
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Result<Request, failure::Error> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let mut parts = line.splitn(3, ' ');
+        let req = match parts.next().unwrap_or("") {
+            "set" => {
+                let id = parts
+                    .next()
+                    .filter(|id| !id.is_empty())
+                    .ok_or_else(|| format_err!("usage: set <widget-id> <text>"))?;
+                Request::Set {
+                    id: id.to_string(),
+                    text: parts.next().unwrap_or("").to_string(),
+                }
+            }
+            "reload" => Request::Reload,
+            "hide" => Request::Hide,
+            "show" => Request::Show,
+            "toggle" => Request::Toggle,
+            "quit" => Request::Quit,
+            "dump-state" => Request::DumpState,
+            "" => bail!("empty request"),
+            other => bail!("unknown request {:?}", other),
+        };
+        Ok(req)
+    }
+}
+
+/// The name of the socket for a bar called `name`, or for a bar
+/// without a name
+fn socket_name(name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("knurling-{}.sock", name),
+        None => SOCKET_NAME.to_string(),
+    }
+}
+
+/// Which bar a socket belongs to, if it's one of ours: `Some(None)` is
+/// the bar without a name
+fn bar_name(socket: &str) -> Option<Option<&str>> {
+    if socket == SOCKET_NAME {
+        return Some(None);
+    }
+    socket
+        .strip_prefix("knurling-")?
+        .strip_suffix(".sock")
+        .filter(|name| !name.is_empty())
+        .map(Some)
+}
+
+/// Work out what a bar should be called from the command line: an
+/// explicit `--name` wins, but a bar started with its own `--config`
+/// gets named after that file, so that two bars with different
+/// configs don't fight over one socket
+pub fn bar_name_for(
+    name: Option<&str>,
+    config: Option<&Path>,
+) -> Result<Option<String>, failure::Error> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => match config.and_then(|c| c.file_stem()) {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => return Ok(None),
+        },
+    };
+    if name.is_empty() || name.contains('/') {
+        bail!("{:?} can't be used as the name of a bar", name);
+    }
+    Ok(Some(name))
+}
+
+fn socket_path(name: Option<&str>) -> Result<PathBuf, failure::Error> {
+    Ok(xdg::BaseDirectories::with_prefix("knurling")?.place_runtime_file(socket_name(name))?)
+}
+
+/// The listening end of the control socket, plus everybody who's
+/// connected to it. All of these live in the main loop's `select`
+/// set, so that a client that's slow to say anything can't hold up
+/// drawing the bar.
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    clients: Vec<Client>,
+}
+
+/// The longest a single request can be before we give up on the
+/// client that's sending it
+const MAX_REQUEST: usize = 64 * 1024;
+
+/// A connection to the control socket, along with anything it's sent
+/// that doesn't make up a whole line yet
+struct Client {
+    stream: UnixStream,
+    partial: Vec<u8>,
+}
+
+impl Server {
+    pub fn bind(name: Option<&str>) -> Result<Server, failure::Error> {
+        Server::bind_at(socket_path(name)?)
+    }
+
+    fn bind_at(path: PathBuf) -> Result<Server, failure::Error> {
+        if path.exists() {
+            // if something's answering on it then there's another
+            // bar running, and we shouldn't yank its socket away
+            if UnixStream::connect(&path).is_ok() {
+                bail!("{} is already in use", path.display());
+            }
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            path,
+            clients: Vec::new(),
+        })
+    }
+
+    pub fn fds(&self) -> Vec<RawFd> {
+        std::iter::once(self.listener.as_raw_fd())
+            .chain(self.clients.iter().map(|c| c.stream.as_raw_fd()))
+            .collect()
+    }
+
+    /// Accept any pending connections, and read whatever's waiting on
+    /// the connections that `ready` says are readable. Every whole
+    /// request gets run through `handle`, and whatever that returns
+    /// gets sent back. This never waits for a client to say anything.
+    pub fn handle(
+        &mut self,
+        ready: impl Fn(RawFd) -> bool,
+        mut handle: impl FnMut(Request) -> Result<String, failure::Error>,
+    ) {
+        if ready(self.listener.as_raw_fd()) {
+            while let Ok((stream, _)) = self.listener.accept() {
+                if let Err(err) = stream.set_nonblocking(true) {
+                    eprintln!("Error talking to IPC client: {}", err);
+                    continue;
+                }
+                // clients tend to send their request right away, so
+                // there's a good chance it's already here
+                self.clients.push(Client {
+                    stream,
+                    partial: Vec::new(),
+                });
+            }
+        }
+
+        self.clients.retain_mut(|client| {
+            if !ready(client.stream.as_raw_fd()) {
+                return true;
+            }
+            match client.handle(&mut handle) {
+                Ok(open) => open,
+                Err(err) => {
+                    eprintln!("Error talking to IPC client: {}", err);
+                    false
+                }
+            }
+        });
+    }
+}
+
+impl Client {
+    /// Read whatever's there, and answer any requests it finishes
+    /// off. This returns whether the client's still there.
+    fn handle(
+        &mut self,
+        handle: &mut impl FnMut(Request) -> Result<String, failure::Error>,
+    ) -> Result<bool, failure::Error> {
+        let mut buf = [0u8; 1024];
+        let open = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break false,
+                Ok(n) => self.partial.extend_from_slice(&buf[..n]),
+                Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => break true,
+                Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err.into()),
+            }
+
+            while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.partial.drain(..=end).collect();
+                self.respond(&line, handle)?;
+            }
+            // nothing we understand is anywhere near this long, so
+            // don't keep holding on to whatever this is
+            if self.partial.len() > MAX_REQUEST {
+                let _ = self.stream.write_all(b"error: request too long\n");
+                bail!("request longer than {} bytes", MAX_REQUEST);
+            }
+        };
+
+        // once they've hung up, whatever's left is the last request
+        if !open && !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.respond(&line, handle)?;
+        }
+        Ok(open)
+    }
+
+    fn respond(
+        &mut self,
+        line: &[u8],
+        handle: &mut impl FnMut(Request) -> Result<String, failure::Error>,
+    ) -> Result<(), failure::Error> {
+        let line = std::str::from_utf8(line)?;
+        let response = match Request::parse(line).and_then(&mut *handle) {
+            Ok(text) if text.is_empty() => "ok\n".to_string(),
+            Ok(text) => text,
+            Err(err) => format!("error: {}\n", err),
+        };
+        // responses are small enough to fit in the socket's buffer,
+        // so this only fails if the client stopped reading, in which
+        // case it's going to get dropped anyway
+        self.stream.write_all(response.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The client side of things: send a single request to a running bar
+/// and print whatever it says back. This returns whether the bar
+/// thought the request succeeded.
+pub fn send(name: Option<&str>, args: &[String]) -> Result<bool, failure::Error> {
+    let request = args.join(" ");
+    // parse it here too, so that typos get caught without needing a
+    // running bar
+    Request::parse(&request)?;
+
+    let path = find_socket(name)?;
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "{}", request)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    print!("{}", response);
+    Ok(!response.starts_with("error:"))
+}
+
+/// Find the socket for the bar called `name`. Without a name, that's
+/// whichever bar is running, as long as there's only the one.
+fn find_socket(name: Option<&str>) -> Result<PathBuf, failure::Error> {
+    let dirs = xdg::BaseDirectories::with_prefix("knurling")?;
+    if let Some(name) = name {
+        return dirs
+            .find_runtime_file(socket_name(Some(name)))
+            .ok_or_else(|| format_err!("Unable to find a running knurling called {:?}", name));
+    }
+
+    let mut sockets: Vec<PathBuf> = dirs
+        .list_runtime_files(".")
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(bar_name)
+                .is_some()
+        })
+        // a bar that crashed can leave its socket behind
+        .filter(|path| UnixStream::connect(path).is_ok())
+        .collect();
+    sockets.sort();
+    match sockets.len() {
+        0 => bail!("Unable to find a running knurling to talk to"),
+        1 => Ok(sockets.remove(0)),
+        _ => {
+            let names: Vec<String> = sockets
+                .iter()
+                .filter_map(|path| path.file_name()?.to_str())
+                .map(|socket| match bar_name(socket) {
+                    Some(Some(name)) => name.to_string(),
+                    _ => "(unnamed)".to_string(),
+                })
+                .collect();
+            bail!(
+                "There's more than one knurling running ({}), so pick one with `--name`",
+                names.join(", ")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn parse_requests() {
+        match Request::parse("set clock hello there\n").unwrap() {
+            Request::Set { id, text } => {
+                assert_eq!(id, "clock");
+                assert_eq!(text, "hello there");
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+        assert!(matches!(Request::parse("reload"), Ok(Request::Reload)));
+        assert!(Request::parse("set").is_err());
+        assert!(Request::parse("").is_err());
+        assert!(Request::parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn socket_names() {
+        assert_eq!(socket_name(None), "knurling.sock");
+        assert_eq!(socket_name(Some("laptop")), "knurling-laptop.sock");
+        assert_eq!(bar_name("knurling.sock"), Some(None));
+        assert_eq!(bar_name("knurling-laptop.sock"), Some(Some("laptop")));
+        assert_eq!(bar_name("knurling-.sock"), None);
+        assert_eq!(bar_name("something-else"), None);
+    }
+
+    #[test]
+    fn names_from_the_command_line() {
+        let config = Path::new("/home/me/.config/knurling/external.toml");
+        assert_eq!(bar_name_for(None, None).unwrap(), None);
+        assert_eq!(
+            bar_name_for(None, Some(config)).unwrap().as_deref(),
+            Some("external")
+        );
+        assert_eq!(
+            bar_name_for(Some("top"), Some(config)).unwrap().as_deref(),
+            Some("top")
+        );
+        assert!(bar_name_for(Some("a/b"), None).is_err());
+    }
+
+    #[test]
+    fn idle_clients_dont_block() {
+        let dir = TempDir::new("ipc");
+        let path = dir.path().join("knurling.sock");
+        let mut server = Server::bind_at(path.clone()).unwrap();
+
+        // one client that connects and then says nothing, and one
+        // that sends its request in two halves
+        let _idle = UnixStream::connect(&path).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.write_all(b"hi").unwrap();
+
+        let mut requests = Vec::new();
+        let start = Instant::now();
+        server.handle(
+            |_| true,
+            |req| {
+                requests.push(format!("{:?}", req));
+                Ok(String::new())
+            },
+        );
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(server.clients.len(), 2);
+        assert!(requests.is_empty());
+
+        client.write_all(b"de\n").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        server.handle(
+            |_| true,
+            |req| {
+                requests.push(format!("{:?}", req));
+                Ok(String::new())
+            },
+        );
+        assert_eq!(requests, vec!["Hide".to_string()]);
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "ok\n");
+        // the one that hung up is gone, and the idle one is still there
+        assert_eq!(server.clients.len(), 1);
+    }
+
+    #[test]
+    fn overlong_requests() {
+        let dir = TempDir::new("ipc-long");
+        let path = dir.path().join("knurling.sock");
+        let mut server = Server::bind_at(path.clone()).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        server.handle(|_| true, |_| Ok(String::new()));
+        assert_eq!(server.clients.len(), 1);
+
+        // a request just under the limit is fine, even if it takes
+        // more than one read to get it all...
+        let text = "x".repeat(MAX_REQUEST - 20);
+        client
+            .write_all(format!("set clock {}\n", text).as_bytes())
+            .unwrap();
+        let mut requests = Vec::new();
+        server.handle(
+            |_| true,
+            |req| {
+                requests.push(req);
+                Ok(String::new())
+            },
+        );
+        assert!(matches!(requests.as_slice(), [Request::Set { .. }]));
+        assert_eq!(server.clients.len(), 1);
+
+        // ...but one that just keeps going gets the client dropped
+        client.set_nonblocking(true).unwrap();
+        let junk = vec![b'x'; 4096];
+        for _ in 0..(MAX_REQUEST / junk.len() + 2) {
+            // once it's been dropped this won't go anywhere
+            let _ = client.write_all(&junk);
+            server.handle(|_| true, |_| Ok(String::new()));
+        }
+        assert!(server.clients.is_empty());
+    }
+}
@@ -1,56 +1,322 @@
 #[macro_use]
 extern crate failure;
 
+mod cli;
 mod config;
+mod ipc;
+#[cfg(test)]
+mod testing;
+mod watch;
 mod widgets;
 mod window;
+mod worker;
 
 use pango::LayoutExt;
 use std::os::unix::io::AsRawFd;
 
-use widgets::Size;
-use window::{Display, Event, Window};
+use std::path::{Path, PathBuf};
+use widgets::{Button, Size};
+use window::{Canvas, Display, Event, Monitor, Window};
+
+/// Set the layout up to use the font from the config. We always use
+/// a bold version, because it looks nicer in a bar.
+fn set_font(layout: &pango::Layout, config: &config::Config) {
+    let mut font = pango::FontDescription::from_string(config.font());
+    font.set_weight(pango::Weight::Bold);
+    layout.set_font_description(&font);
+}
+
+/// Where the bar goes on `monitor`
+fn bar_geometry(monitor: &Monitor, config: &config::Config) -> Size {
+    let (margin_x, margin_y) = config.margins();
+    let ht = config.get_height();
+    // margins too big for the monitor would leave the bar with no
+    // width at all, or push it off the monitor entirely, so they only
+    // go as far as leaving a bar that's a pixel wide and on-screen
+    let margin_x = margin_x.min((monitor.width - 1) / 2).max(0);
+    let margin_y = margin_y.min(monitor.height - ht).max(0);
+    let yo = match config.position() {
+        config::Position::Top => monitor.y + margin_y,
+        config::Position::Bottom => monitor.y + monitor.height - margin_y - ht,
+    };
+    Size {
+        wd: monitor.width - 2 * margin_x,
+        ht,
+        xo: monitor.x + margin_x,
+        yo,
+    }
+}
+
+/// The monitors that the config wants a bar on, along with where
+/// each of them is in the list of all of them. If none of them match
+/// then we'd rather have a bar somewhere than nowhere, so the first
+/// one gets it.
+fn shown_monitors(monitors: &[Monitor], config: &config::Config) -> Vec<(usize, Monitor)> {
+    let shown: Vec<(usize, Monitor)> = monitors
+        .iter()
+        .enumerate()
+        .filter(|(i, m)| config.shows_on(*i, m))
+        .map(|(i, m)| (i, m.clone()))
+        .collect();
+    if shown.is_empty() {
+        eprintln!("None of the `monitors` in the config are connected, so using the first one");
+        return monitors.iter().take(1).cloned().enumerate().collect();
+    }
+    shown
+}
+
+/// Tell the window manager to keep other windows out of the bar's
+/// way. Struts are measured from the edges of the whole screen, so a
+/// bar on a monitor that doesn't reach the bottom of the screen needs
+/// to reserve the gap below that monitor as well. The partial struts
+/// then say which stretch of that edge the bar is actually on, so
+/// other monitors along the same edge don't lose any room.
+fn set_struts(
+    w: &mut Window,
+    size: Size,
+    position: config::Position,
+    screen_ht: i32,
+) -> Result<(), failure::Error> {
+    let partial = struts(size, position, screen_ht);
+    // the old-style struts can't say which part of the edge they're
+    // for, so window managers that only know about them will keep
+    // windows off that whole edge
+    w.change_property("_NET_WM_STRUT", &partial[..4])?;
+    w.change_property("_NET_WM_STRUT_PARTIAL", &partial)
+}
+
+/// The `_NET_WM_STRUT_PARTIAL` for a bar at `size`, which is how much
+/// to reserve at the left, right, top and bottom of the screen,
+/// followed by where along each of those edges it's reserved
+fn struts(size: Size, position: config::Position, screen_ht: i32) -> [i64; 12] {
+    // the ends of the range are both inclusive
+    let start_x = size.xo as i64;
+    let end_x = (size.xo + size.wd - 1) as i64;
+    let (top, top_x, bottom, bottom_x) = match position {
+        config::Position::Top => ((size.yo + size.ht) as i64, (start_x, end_x), 0, (0, 0)),
+        config::Position::Bottom => (0, (0, 0), (screen_ht - size.yo) as i64, (start_x, end_x)),
+    };
+    [
+        0, 0, top, bottom, 0, 0, 0, 0, top_x.0, top_x.1, bottom_x.0, bottom_x.1,
+    ]
+}
+
+/// Cairo and Pango state for drawing on one window, plus which of the
+/// config's bars goes there and where each of its widgets ended up the
+/// last time we drew it
+type DrawContext = (Canvas, pango::Layout, Size, usize, Vec<config::Extent>);
+
+fn mk_context(
+    w: &mut Window,
+    config: &config::Config,
+    bar: usize,
+    input: &str,
+) -> Result<DrawContext, failure::Error> {
+    // let's grab the cairo context here
+    let mut canvas = Canvas::new(w)?;
+
+    let layout = pangocairo::functions::create_layout(canvas.context())
+        .ok_or_else(|| format_err!("unable to create layout"))?;
+
+    set_font(&layout, config);
+
+    // do an initial pass at drawing the bar! We hang on to where
+    // each widget ended up so we can tell what gets clicked.
+    let extents = config.draw(canvas.context(), &layout, input, w.size(), bar)?;
+    canvas.show();
+    Ok((canvas, layout, w.size(), bar, extents))
+}
+
+/// Make a new window for a bar to go in. This doesn't set its
+/// struts, since those need setting again whenever it moves anyway.
+fn mk_window(d: &Display, size: Size) -> Result<Window<'_>, failure::Error> {
+    let mut w = Window::create(d, size)?;
+    // set some window-manager properties: this is a dock
+    w.change_property("_NET_WM_WINDOW_TYPE", &["_NET_WM_WINDOW_TYPE_DOCK"])?;
+
+    // we won't ever see this, but for good measure.
+    w.set_title("rbar")?;
+    // we care about some input events!
+    w.set_input_masks()?;
+    w.set_protocols()?;
+    Ok(w)
+}
+
+/// Make sure there's a bar on every monitor that ought to have one,
+/// each the right size and in the right place. This is what sets the
+/// windows up to begin with, and it gets done again whenever monitors
+/// come and go or the config changes. Windows get reused where they
+/// can be, so this only makes new ones when there are more monitors
+/// than before.
+fn arrange<'t>(
+    d: &'t Display,
+    config: &config::Config,
+    ws: &mut Vec<Window<'t>>,
+    ctxs: &mut Vec<DrawContext>,
+    input: &str,
+    visible: bool,
+) -> Result<(), failure::Error> {
+    let monitors = shown_monitors(&d.get_monitors()?, config);
+    let screen_ht = d.get_height();
+
+    // the surfaces have to go before their windows do, and they'll
+    // all need remaking at the new sizes anyway
+    ctxs.clear();
+    ws.truncate(monitors.len());
+    for (n, (i, monitor)) in monitors.iter().enumerate() {
+        let size = bar_geometry(monitor, config);
+        match ws.get_mut(n) {
+            Some(w) => w.set_geometry(size),
+            None => {
+                let mut w = mk_window(d, size)?;
+                if visible {
+                    w.map();
+                }
+                ws.push(w);
+            }
+        }
+        let w = &mut ws[n];
+        // this should push other windows out of the way
+        set_struts(w, size, config.position(), screen_ht)?;
+        ctxs.push(mk_context(w, config, config.bar_for(*i, monitor), input)?);
+    }
+    Ok(())
+}
+
+/// Read the config in again and swap it in for the old one. If that
+/// doesn't work, the old config stays where it is. Without a
+/// `--config` flag we look for the file again, because it might not
+/// have been there last time.
+fn reload<'t>(
+    flag: Option<&Path>,
+    config: &mut config::Config,
+    d: &'t Display,
+    ws: &mut Vec<Window<'t>>,
+    ctxs: &mut Vec<DrawContext>,
+    input: &str,
+    visible: bool,
+) -> Result<(), failure::Error> {
+    let path = config_path(flag.map(Path::to_path_buf))?;
+    *config = config::Config::load(path.as_deref())?;
+    // the bars might have moved, changed size, or be wanted on
+    // different monitors now
+    arrange(d, config, ws, ctxs, input, visible)
+}
+
+/// Work out which config file we ought to be using, if any
+fn config_path(flag: Option<PathBuf>) -> Result<Option<PathBuf>, failure::Error> {
+    match flag {
+        Some(path) => Ok(Some(path)),
+        None => config::Config::find_config_path(),
+    }
+}
+
+/// Print out everything wrong with the config, returning whether
+/// it's okay to use
+fn check(path: Option<PathBuf>) -> Result<bool, failure::Error> {
+    let (name, body) = match config_path(path)? {
+        Some(path) => {
+            let body = std::fs::read_to_string(&path)
+                .map_err(|err| format_err!("Unable to read {}: {}", path.display(), err))?;
+            (path.display().to_string(), body)
+        }
+        None => (
+            "the built-in default config".to_string(),
+            config::DEFAULT_CONFIG.to_string(),
+        ),
+    };
+    let report = config::check(&body);
+    for warning in report.warnings.iter() {
+        println!("warning: {}", warning);
+    }
+    if report.problems.is_empty() {
+        println!("{}: ok", name);
+        return Ok(true);
+    }
+    println!("{}: found {} problem(s)", name, report.problems.len());
+    for problem in report.problems {
+        println!("  {}", problem);
+    }
+    Ok(false)
+}
 
 fn main() -> Result<(), failure::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::Command::parse(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("knurling: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let (config_flag, name) = match command {
+        cli::Command::Run { config, name } => (config, name),
+        cli::Command::Check { config } => {
+            std::process::exit(if check(config)? { 0 } else { 1 });
+        }
+        cli::Command::PrintDefaultConfig => {
+            print!("{}", config::DEFAULT_CONFIG);
+            return Ok(());
+        }
+        // `knurling msg ...` doesn't start a bar, it just talks to one
+        cli::Command::Msg { name, args } => {
+            if !ipc::send(name.as_deref(), &args)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        cli::Command::Help => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        }
+    };
+
+    // two bars with their own configs get their own sockets too
+    let name = ipc::bar_name_for(name.as_deref(), config_flag.as_deref())?;
     // set up the display and the window
-    let mut config = config::Config::find_config()?;
-    let height = config.get_height();
+    let config_path = config_path(config_flag.clone())?;
+    if config_path.is_none() {
+        eprintln!("No `knurling.toml` found, so using the default config");
+    }
+    let mut config = config::Config::load(config_path.as_deref())?;
 
-    let mut d = Display::create()?;
-    let mut ws = Vec::new();
+    // SIGHUP means reload, whether or not there's a file to reload
+    // from yet
+    let sighup = watch::Sighup::install()?;
+    // we can live without noticing config changes, so this is
+    // allowed to fail. If there's no config file yet, we watch for
+    // one turning up where it'd usually go.
+    let watch_path = match config_path {
+        Some(ref path) => Ok(path.clone()),
+        None => config::Config::default_config_path(),
+    };
+    let watcher = match watch_path.and_then(|path| watch::ConfigWatcher::new(&path)) {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            eprintln!("Not watching for config changes: {}", err);
+            None
+        }
+    };
 
-    for (x_off, wd) in d.get_widths()? {
-        let size = Size {
-            wd,
-            ht: height,
-            xo: x_off,
-            yo: 0,
-        };
-        let mut w = Window::create(&d, size)?;
-        // set some window-manager properties: this is a dock
-        w.change_property("_NET_WM_WINDOW_TYPE", &["_NET_WM_WINDOW_TYPE_DOCK"])?;
-        // ...and should push other windows out of the way
-        w.change_property("_NET_WM_STRUT", &[x_off as i64, 0, size.ht as i64, 0])?;
-        w.change_property(
-            "_NET_WM_STRUT_PARTIAL",
-            &[0, 0, size.ht as i64, 0, 0, 0, 0, 0, 0, size.wd as i64, 0, 0],
-        )?;
-
-        // we won't ever see this, but for good measure.
-        w.set_title("rbar")?;
-        // we care about some input events!
-        w.set_input_masks()?;
-        w.set_protocols()?;
-        // and now show it!
-        w.map();
-        ws.push(w);
-    }
+    let d = Display::create()?;
+    // we want to know when monitors get plugged in or unplugged, so
+    // the bars can follow them
+    d.select_screen_changes();
+    let mut ws = Vec::new();
+    let mut visible = true;
 
-    // we do some grossness with file descriptors later, so we need
-    // the file descriptors we care about here
-    let window_fds: Vec<i32> = ws.iter_mut().map({ |w| w.get_fd() }).collect();
     let stdin_fd = std::io::stdin().as_raw_fd();
 
+    // the control socket is a nice-to-have, so if we can't set it up
+    // (e.g. because another bar already has it) then we carry on
+    let mut server = match ipc::Server::bind(name.as_deref()) {
+        Ok(server) => Some(server),
+        Err(err) => {
+            eprintln!("Not listening for IPC requests: {}", err);
+            None
+        }
+    };
+
     // To begin with, our left-hand side---which normally is whatever
     // was last passed in on stdin---will start as a generic
     // message...
@@ -58,92 +324,315 @@ fn main() -> Result<(), failure::Error> {
     // And let's get a buffered stdin handle now
     let mut stdin = std::io::BufReader::new(std::io::stdin());
 
-    // In the absence of other events, let's refresh every five
-    // seconds. Or whatever.
-    let mut timer = libc::timeval {
-        tv_sec: 5,
-        tv_usec: 0,
-    };
-
     let mut ctxs = Vec::new();
-    for w in ws.iter_mut() {
-        // let's grab the cairo context here
-        let surf = w.get_cairo_surface();
-        let ctx = cairo::Context::new(&surf);
-
-        let layout = pangocairo::functions::create_layout(&ctx)
-            .ok_or_else(|| format_err!("unable to create layout"))?;
+    arrange(&d, &config, &mut ws, &mut ctxs, &input, visible)?;
 
-        // allow for the whole width of the bar, minus a small fixed amount
-        layout.set_width((w.width - 20) * pango::SCALE);
-        // this should also be configurable, but Fira Mono is a good font
-        let mut font = pango::FontDescription::from_string(config.font());
-        font.set_weight(pango::Weight::Bold);
-        layout.set_font_description(&font);
-
-        // do an initial pass at drawing the bar!
-        config.draw(&ctx, &layout, &input, w.size())?;
-
-        ctxs.push((ctx, layout, w.size()));
-    }
+    // the windows all share one connection to the X server, and we
+    // need to keep listening on it even if every monitor's gone, or
+    // we'd never hear about one coming back
+    let x_fd = d.get_fd();
 
-    let max_fd = window_fds.iter().max().unwrap_or(&0) + 1;
     // we're gonna keep looping until we don't
-    loop {
+    'main: loop {
         let mut fds = std::mem::MaybeUninit::uninit();
+        // widgets can start and stop caring about file descriptors
+        // (e.g. when a child process gets restarted) so we need to
+        // ask for these fresh every time around
+        let widget_fds = config.fds();
+        let server_fds: Vec<i32> = server.iter().flat_map(|s| s.fds()).collect();
+        let watcher_fds: Vec<i32> = std::iter::once(sighup.fd())
+            .chain(watcher.iter().map(|w| w.fd()))
+            .collect();
+        let max_fd = std::iter::once(&x_fd)
+            .chain(widget_fds.iter())
+            .chain(server_fds.iter())
+            .chain(watcher_fds.iter())
+            .max()
+            .unwrap_or(&0)
+            + 1;
 
-        unsafe {
+        // In the absence of other events, sleep until the next time
+        // some widget wants updating. If nothing ever does, then we
+        // can just wait for events forever.
+        let mut timer = config.next_deadline().map(|deadline| {
+            let wait = deadline.saturating_duration_since(std::time::Instant::now());
+            libc::timeval {
+                tv_sec: wait.as_secs() as libc::time_t,
+                tv_usec: wait.subsec_micros() as libc::suseconds_t,
+            }
+        });
+
+        let ready = unsafe {
             // set up the FD set to be the X11 fd and the state of stdin
             libc::FD_ZERO(fds.as_mut_ptr());
             fds.assume_init();
-            for fd in window_fds.iter() {
+            libc::FD_SET(x_fd, fds.as_mut_ptr());
+            for fd in widget_fds
+                .iter()
+                .chain(server_fds.iter())
+                .chain(watcher_fds.iter())
+            {
                 libc::FD_SET(*fd, fds.as_mut_ptr());
             }
             libc::FD_SET(stdin_fd, fds.as_mut_ptr());
-            timer.tv_sec = 5;
 
-            // this will block until there's input on either of the
-            // above FDs or until five seconds have passed, whichever comes first
+            // this will block until there's input on any of the
+            // above FDs or until the next deadline, whichever comes first
             libc::select(
                 max_fd,
                 fds.as_mut_ptr(),
                 std::ptr::null_mut(),
                 std::ptr::null_mut(),
-                &mut timer,
-            );
+                timer
+                    .as_mut()
+                    .map_or(std::ptr::null_mut(), |t| t as *mut libc::timeval),
+            )
+        };
+        if ready < 0 {
+            // the sets are left as they were when this happens, so
+            // none of them mean anything. If it was SIGHUP that
+            // interrupted us then it's left a byte in its pipe, and
+            // we'll see that next time around.
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            bail!("Unable to wait for input: {}", err);
         }
+        // nothing's readable if we just ran out of time
+        let is_set = |fd| ready > 0 && unsafe { libc::FD_ISSET(fd, fds.as_ptr()) };
 
         // if we _did_ have input on stdin, then read it in: that'll
         // be our new left-hand text
-        if unsafe { libc::FD_ISSET(stdin_fd, fds.as_mut_ptr()) } {
+        if is_set(stdin_fd) {
             use std::io::BufRead;
             input = String::new();
             stdin.read_line(&mut input)?;
             if input.is_empty() {
                 break;
             }
-            for (ctx, layout, sz) in ctxs.iter() {
-                config.draw(&ctx, &layout, &input, *sz)?;
+            for (canvas, layout, sz, bar, extents) in ctxs.iter_mut() {
+                *extents = config.draw(canvas.context(), layout, &input, *sz, *bar)?;
+                canvas.show();
+            }
+        }
+
+        // if any widgets have new input waiting, let them read it
+        for fd in widget_fds {
+            if is_set(fd) {
+                config.handle_readable(fd);
+            }
+        }
+
+        // if somebody's talking to us over the control socket, then
+        // do what they say
+        if let Some(ref mut server) = server {
+            if server_fds.iter().any(|fd| is_set(*fd)) {
+                let mut quit = false;
+                let was_visible = visible;
+                server.handle(is_set, |req| {
+                    match req {
+                        ipc::Request::Set { id, text } => config.set_text(&id, &text)?,
+                        ipc::Request::Reload => reload(
+                            config_flag.as_deref(),
+                            &mut config,
+                            &d,
+                            &mut ws,
+                            &mut ctxs,
+                            &input,
+                            visible,
+                        )?,
+                        ipc::Request::Hide => visible = false,
+                        ipc::Request::Show => visible = true,
+                        ipc::Request::Toggle => visible = !visible,
+                        ipc::Request::Quit => quit = true,
+                        ipc::Request::DumpState => {
+                            return Ok(format!("visible {}\n{}", visible, config.dump_state()))
+                        }
+                    }
+                    Ok(String::new())
+                });
+                if visible != was_visible {
+                    for w in ws.iter_mut() {
+                        if visible {
+                            w.map();
+                        } else {
+                            w.unmap();
+                        }
+                    }
+                }
+                if quit {
+                    break 'main;
+                }
+            }
+        }
+
+        // if the config file changed (or somebody sent us SIGHUP)
+        // then reload it, but don't give up if the new one's broken
+        let mut changed = is_set(sighup.fd()) && sighup.check();
+        if let Some(ref watcher) = watcher {
+            changed |= is_set(watcher.fd()) && watcher.check();
+        }
+        if changed {
+            if let Err(err) = reload(
+                config_flag.as_deref(),
+                &mut config,
+                &d,
+                &mut ws,
+                &mut ctxs,
+                &input,
+                visible,
+            ) {
+                eprintln!("{}", err);
+                config.flash_error(&err.to_string());
             }
         }
 
         // if we have X11 events, handle them. If any one was a quit
         // event, then just... quit.
-        for w in ws.iter_mut() {
-            while w.has_events() {
-                match w.handle() {
-                    Some(Event::QuitEvent) => break,
-                    _e => (),
+        let mut screen_changed = false;
+        while d.has_events() {
+            match d.handle() {
+                Some(Event::QuitEvent) => break 'main,
+                Some(Event::MouseEvent {
+                    window,
+                    button,
+                    x,
+                    y,
+                }) => {
+                    // all the windows share a connection, so find
+                    // out which one was clicked on. If the bars didn't
+                    // all get set up last time, this one might not
+                    // have anything drawn on it to click on.
+                    let idx = ws.iter().position(|w| w.window == window);
+                    if let Some((.., extents)) = idx.and_then(|idx| ctxs.get(idx)) {
+                        config.click(extents, Button::from_x11(button), x, y);
+                    }
                 }
+                // whatever was on the window might be gone now, so
+                // it'll need drawing again even if nothing changed
+                Some(Event::ShowEvent) => {
+                    for (canvas, ..) in ctxs.iter_mut() {
+                        canvas.invalidate();
+                    }
+                }
+                Some(Event::ScreenEvent) => screen_changed = true,
+                _e => (),
             }
         }
-
-        for (ctx, layout, sz) in ctxs.iter() {
-            // otherwise, draw the thing!
-            config.draw(&ctx, &layout, &input, *sz)?;
+        // changing a monitor tends to come with a flurry of these, so
+        // we wait until we've seen all of them before doing anything
+        if screen_changed {
+            arrange(&d, &config, &mut ws, &mut ctxs, &input, visible)?;
         }
+
+        // give any widgets that are due a chance to update...
         config.update();
+        for (canvas, layout, sz, bar, extents) in ctxs.iter_mut() {
+            // ...and then draw the thing! This only goes on the window
+            // if it looks any different.
+            *extents = config.draw(canvas.context(), layout, &input, *sz, *bar)?;
+            canvas.show();
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(x: i32, y: i32, width: i32, height: i32) -> Monitor {
+        Monitor {
+            name: None,
+            primary: false,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn config(options: &str) -> config::Config {
+        config::Config::from_str(&format!("{}\n[[widgets]]\nname = \"time\"\n", options)).unwrap()
+    }
+
+    #[test]
+    fn bar_placement() {
+        let m = monitor(1920, 0, 2560, 1440);
+        let top = config("height = 24");
+        assert_eq!(
+            bar_geometry(&m, &top),
+            Size {
+                wd: 2560,
+                ht: 24,
+                xo: 1920,
+                yo: 0
+            }
+        );
+
+        let bottom = config("height = 24\nposition = \"bottom\"\nmargin_x = 10\nmargin_y = 5");
+        assert_eq!(
+            bar_geometry(&m, &bottom),
+            Size {
+                wd: 2540,
+                ht: 24,
+                xo: 1930,
+                yo: 1440 - 5 - 24
+            }
+        );
+    }
+
+    #[test]
+    fn margins_bigger_than_the_monitor() {
+        let m = monitor(0, 0, 100, 50);
+        let config = config("height = 24\nmargin_x = 60\nmargin_y = 100");
+        assert_eq!(
+            bar_geometry(&m, &config),
+            Size {
+                wd: 2,
+                ht: 24,
+                xo: 49,
+                yo: 26
+            }
+        );
+    }
+
+    #[test]
+    fn struts_on_more_than_one_monitor() {
+        use config::Position::{Bottom, Top};
+        // a 1080p monitor on the left of a 1440p one, lined up along
+        // the top, so the screen's 1440 tall
+        let left = Size {
+            wd: 1920,
+            ht: 24,
+            xo: 0,
+            yo: 0,
+        };
+        assert_eq!(
+            struts(left, Top, 1440),
+            [0, 0, 24, 0, 0, 0, 0, 0, 0, 1919, 0, 0]
+        );
+        let right = Size {
+            xo: 1920,
+            wd: 2560,
+            ..left
+        };
+        assert_eq!(
+            struts(right, Top, 1440),
+            [0, 0, 24, 0, 0, 0, 0, 0, 1920, 4479, 0, 0]
+        );
+
+        // at the bottom of the shorter monitor, the bar has to keep
+        // windows out of the gap underneath it as well
+        let left = Size {
+            yo: 1080 - 24,
+            ..left
+        };
+        assert_eq!(
+            struts(left, Bottom, 1440),
+            [0, 0, 0, 384, 0, 0, 0, 0, 0, 0, 0, 1919]
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! Bits and pieces that the tests share
+
+use std::path::{Path, PathBuf};
+
+/// A directory for a test's fixtures to go in. It gets removed again
+/// once the test's finished with it, even if the test failed, so
+/// failures don't leave things lying around in `/tmp`.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Make a fresh directory. `name` has to be different for each
+    /// test, because tests run at the same time as one another.
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("knurling-{}-{}", name, std::process::id()));
+        // this might be left over from a run that got killed
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write a fixture file, making any directories it needs
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path.join(name);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,188 @@
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+// the write end of the SIGHUP pipe, which has to be a global because
+// signal handlers don't get to have any other state
+static SIGHUP_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_sighup(_: libc::c_int) {
+    let fd = SIGHUP_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe {
+            libc::write(fd, [0u8].as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Lets us know when we've been sent SIGHUP, which is the other way
+/// of asking for a reload. This gets set up no matter what, because
+/// otherwise SIGHUP would just kill the bar.
+pub struct Sighup {
+    read: RawFd,
+    write: RawFd,
+}
+
+impl Sighup {
+    pub fn install() -> Result<Sighup, failure::Error> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } != 0 {
+            bail!(
+                "Unable to create SIGHUP pipe: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        SIGHUP_FD.store(fds[1], Ordering::SeqCst);
+        unsafe {
+            libc::signal(
+                libc::SIGHUP,
+                on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+        Ok(Sighup {
+            read: fds[0],
+            write: fds[1],
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.read
+    }
+
+    /// Read whatever's waiting, and return whether we got SIGHUP
+    pub fn check(&self) -> bool {
+        drain(self.read)
+    }
+}
+
+impl Drop for Sighup {
+    fn drop(&mut self) {
+        SIGHUP_FD.store(-1, Ordering::SeqCst);
+        unsafe {
+            libc::signal(libc::SIGHUP, libc::SIG_DFL);
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+/// Keeps an eye on the config file. The file doesn't have to exist
+/// yet, but the directory it'd go in does.
+pub struct ConfigWatcher {
+    inotify_fd: RawFd,
+    file_name: PathBuf,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path) -> Result<ConfigWatcher, failure::Error> {
+        // we watch the directory rather than the file itself, because
+        // lots of editors save by writing a new file and renaming it
+        // over the old one, after which a watch on the file would
+        // just go quiet
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format_err!("{} isn't a file", path.display()))?
+            .into();
+
+        let inotify_fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if inotify_fd < 0 {
+            bail!(
+                "Unable to set up inotify: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        let dir_c = CString::new(dir.as_os_str().as_bytes())?;
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+        if unsafe { libc::inotify_add_watch(inotify_fd, dir_c.as_ptr(), mask) } < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::close(inotify_fd) };
+            bail!("Unable to watch {}: {}", dir.display(), err);
+        }
+
+        Ok(ConfigWatcher {
+            inotify_fd,
+            file_name,
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.inotify_fd
+    }
+
+    /// Read whatever's waiting, and return whether it means the
+    /// config file changed
+    pub fn check(&self) -> bool {
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+        let mut changed = false;
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = unsafe {
+                libc::read(
+                    self.inotify_fd,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n <= 0 {
+                break;
+            }
+            let mut offset = 0;
+            while offset + HEADER <= n as usize {
+                let event = unsafe {
+                    std::ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+                };
+                let name = &buf[offset + HEADER..offset + HEADER + event.len as usize];
+                // the name is padded out with NULs
+                let name = name.split(|b| *b == 0).next().unwrap_or(&[]);
+                if name == self.file_name.as_os_str().as_bytes() {
+                    changed = true;
+                }
+                offset += HEADER + event.len as usize;
+            }
+        }
+        changed
+    }
+}
+
+/// Empty out a pipe, returning whether there was anything in it
+fn drain(fd: RawFd) -> bool {
+    let mut buf = [0u8; 64];
+    let mut any = false;
+    while unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {
+        any = true;
+    }
+    any
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.inotify_fd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn notices_a_new_config_file() {
+        let dir = TempDir::new("watch");
+        let watcher = ConfigWatcher::new(&dir.path().join("knurling.toml")).unwrap();
+        assert!(!watcher.check());
+        // somebody else's file doesn't count...
+        dir.write("other.toml", "");
+        assert!(!watcher.check());
+        // ...but ours does, even though it wasn't there to begin with
+        dir.write("knurling.toml", "");
+        assert!(watcher.check());
+    }
+}
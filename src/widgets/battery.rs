@@ -1,10 +1,12 @@
-use crate::widgets::widget::{Drawing, Located, Widget};
+use crate::widgets::widget::{Drawing, Frequency, Located, Widget};
+
+use std::sync::Mutex;
 
 pub struct Battery {
     file_list: Vec<std::path::PathBuf>,
     charging: Option<std::path::PathBuf>,
-    last_status: f64,
-    last_charging: bool,
+    // the last charge level we saw, and whether we were charging
+    last_status: Mutex<(f64, bool)>,
 }
 
 impl Battery {
@@ -28,8 +30,7 @@ impl Battery {
             } else {
                 None
             },
-            last_status: 1.0f64,
-            last_charging: false,
+            last_status: Mutex::new((1.0f64, false)),
         })
     }
 
@@ -58,11 +59,11 @@ impl Battery {
 
 impl Widget for Battery {
     fn draw(&self, d: &Drawing, loc: Located) -> i32 {
-        let amt = self.last_status;
+        let (amt, charging) = *self.last_status.lock().unwrap();
         let sz = d.size.ht - (d.buffer as i32 * 2);
         let x = loc.target_x(d, sz);
         match amt {
-            _ if self.last_charging => d.ctx.set_source_rgb(0.5, 0.5, 1.0),
+            _ if charging => d.ctx.set_source_rgb(0.5, 0.5, 1.0),
             x if x < 0.1 => d.ctx.set_source_rgb(1.0, 0.0, 0.0),
             x if x < 0.5 => d.ctx.set_source_rgb(1.0, 1.0, 0.0),
             _ => d.ctx.set_source_rgb(0.0, 1.0, 0.5),
@@ -84,17 +85,24 @@ impl Widget for Battery {
         sz
     }
 
-    fn update_frequency(&self) -> Option<u64> {
-        Some(10)
+    fn update_frequency(&self) -> Option<Frequency> {
+        Some(Frequency::seconds(10))
     }
 
-    fn update(&mut self) {
-        if let Ok(status) = self.read_status() {
-            self.last_status = status;
-        }
+    fn update_blocks(&self) -> bool {
+        true
+    }
 
-        if let Ok(charging) = self.is_charging() {
-            self.last_charging = charging;
+    fn update(&self) {
+        let status = self.read_status();
+        let charging = self.is_charging();
+
+        let mut last = self.last_status.lock().unwrap();
+        if let Ok(status) = status {
+            last.0 = status;
+        }
+        if let Ok(charging) = charging {
+            last.1 = charging;
         }
     }
 }
@@ -0,0 +1,210 @@
+use crate::widgets::template::Template;
+use crate::widgets::widget::{Drawing, Frequency, Located, Widget};
+
+use std::io::Read;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_ERROR_TEXT: &str = "[{status}]";
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Line {
+    First,
+    Last,
+}
+
+pub struct Command {
+    command: String,
+    interval: Duration,
+    timeout: Option<Duration>,
+    line: Line,
+    error_text: Template,
+    text: Mutex<String>,
+}
+
+impl Command {
+    pub fn new(
+        command: String,
+        interval: Duration,
+        timeout: Option<Duration>,
+        line: Line,
+        error_text: &str,
+    ) -> Result<Command, failure::Error> {
+        let error_text = Template::parse("error_text", error_text, &["status"])?;
+        Ok(Command {
+            command,
+            interval,
+            timeout,
+            line,
+            error_text,
+            text: Mutex::new("...".to_string()),
+        })
+    }
+}
+
+/// Start a command via `/bin/sh -c` and forget about it. This is for
+/// things like click actions, where we don't care about the output.
+pub fn spawn(command: &str) {
+    let child = std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn();
+    match child {
+        // somebody still has to wait on it so it doesn't stick
+        // around as a zombie, but that somebody doesn't have to be us
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(err) => eprintln!("Unable to run {:?}: {}", command, err),
+    }
+}
+
+/// The ways a command run can end up not giving us any output
+enum Failure {
+    Status(ExitStatus),
+    Timeout,
+    Io(std::io::Error),
+}
+
+impl Failure {
+    fn describe(&self) -> String {
+        match self {
+            Failure::Status(status) => match (status.code(), status.signal()) {
+                (Some(code), _) => format!("exit {}", code),
+                (None, Some(sig)) => format!("signal {}", sig),
+                (None, None) => "failed".to_string(),
+            },
+            Failure::Timeout => "timeout".to_string(),
+            Failure::Io(err) => err.to_string(),
+        }
+    }
+}
+
+/// Run a command via `/bin/sh -c`, killing it (and anything it
+/// spawned) if it takes longer than the timeout
+fn run(command: &str, timeout: Option<Duration>) -> Result<String, Failure> {
+    let mut child = std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(Failure::Io)?;
+
+    // read stdout on the side, so that a chatty command can't fill up
+    // the pipe and block forever while we're waiting for it to exit
+    let mut stdout = child.stdout.take().expect("stdout was piped");
+    let reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stdout.read_to_end(&mut buf);
+        buf
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(Failure::Io)? {
+            Some(status) => break status,
+            None if timeout.is_some_and(|t| start.elapsed() > t) => {
+                unsafe {
+                    libc::kill(-(child.id() as i32), libc::SIGKILL);
+                }
+                let _ = child.wait();
+                return Err(Failure::Timeout);
+            }
+            None => std::thread::sleep(Duration::from_millis(20)),
+        }
+    };
+
+    let output = reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(Failure::Status(status));
+    }
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// The line of the command's output that we show, skipping over any
+/// blank ones
+fn pick_line(output: &str, line: Line) -> String {
+    let mut lines = output.lines().filter(|l| !l.trim().is_empty());
+    match line {
+        Line::First => lines.next(),
+        Line::Last => lines.next_back(),
+    }
+    .unwrap_or("")
+    .to_string()
+}
+
+impl Widget for Command {
+    fn draw(&self, d: &Drawing, loc: Located) -> i32 {
+        loc.draw_text(d, &self.text.lock().unwrap())
+    }
+
+    fn update_frequency(&self) -> Option<Frequency> {
+        Some(Frequency::Every(self.interval))
+    }
+
+    // this runs on a worker thread, so it's fine for it to block
+    // until the command finishes
+    fn update_blocks(&self) -> bool {
+        true
+    }
+
+    fn update(&self) {
+        let text = match run(&self.command, self.timeout) {
+            Ok(output) => pick_line(&output, self.line),
+            Err(err) => {
+                let status = err.describe();
+                eprintln!("Command {:?} failed: {}", self.command, status);
+                self.error_text.render(|_| status.clone())
+            }
+        };
+        *self.text.lock().unwrap() = text;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(command: &str, timeout: Option<Duration>) -> String {
+        match run(command, timeout) {
+            Ok(output) => panic!("{:?} worked, and printed {:?}", command, output),
+            Err(err) => err.describe(),
+        }
+    }
+
+    #[test]
+    fn first_and_last_lines() {
+        let output = run("printf '\\n  \\nfirst\\nmiddle\\nlast\\n\\n'", None)
+            .ok()
+            .unwrap();
+        assert_eq!(pick_line(&output, Line::First), "first");
+        assert_eq!(pick_line(&output, Line::Last), "last");
+        assert_eq!(pick_line("", Line::Last), "");
+    }
+
+    #[test]
+    fn failing_commands() {
+        assert_eq!(failure("echo partial; exit 3", None), "exit 3");
+        assert_eq!(failure("kill -9 $$", None), "signal 9");
+        let error_text = Template::parse("error_text", DEFAULT_ERROR_TEXT, &["status"]).unwrap();
+        let status = failure("exit 1", None);
+        assert_eq!(error_text.render(|_| status.clone()), "[exit 1]");
+    }
+
+    #[test]
+    fn timeouts() {
+        let start = Instant::now();
+        // the background job has the same stdout, so this would
+        // hang around until it finished if we waited to read it all
+        let status = failure("sleep 10 & sleep 10", Some(Duration::from_millis(100)));
+        assert_eq!(status, "timeout");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
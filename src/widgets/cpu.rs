@@ -0,0 +1,209 @@
+use crate::widgets::widget::{Drawing, Frequency, Located, Widget};
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    Aggregate,
+    #[serde(alias = "per_core")]
+    PerCore,
+}
+
+/// The idle and total jiffies for a single `cpu` line of
+/// `/proc/stat`: usage is the change in these between two samples
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    idle: u64,
+    total: u64,
+}
+
+struct Readings {
+    last_samples: Vec<Sample>,
+    // the first entry is the aggregate, and the rest are the
+    // individual cores in order
+    usage: Vec<f64>,
+}
+
+pub struct Cpu {
+    stat_path: PathBuf,
+    mode: Mode,
+    interval: Duration,
+    readings: Mutex<Readings>,
+}
+
+impl Cpu {
+    pub fn new(proc_root: &Path, mode: Mode, interval: Duration) -> Result<Cpu, failure::Error> {
+        let stat_path = proc_root.join("stat");
+        // take an initial sample so that the first real update has
+        // something to compare against
+        let last_samples = read_samples(&stat_path)?;
+        let usage = vec![0.0; last_samples.len()];
+        Ok(Cpu {
+            stat_path,
+            mode,
+            interval,
+            readings: Mutex::new(Readings {
+                last_samples,
+                usage,
+            }),
+        })
+    }
+}
+
+fn read_samples(path: &Path) -> Result<Vec<Sample>, failure::Error> {
+    let body = std::fs::read_to_string(path)?;
+    let mut samples = Vec::new();
+    for line in body.lines().filter(|l| l.starts_with("cpu")) {
+        let fields: Result<Vec<u64>, _> = line.split_whitespace().skip(1).map(str::parse).collect();
+        let fields = fields?;
+        if fields.len() < 4 {
+            bail!("Malformed line in {}: {:?}", path.display(), line);
+        }
+        // the fields are user, nice, system, idle, iowait, irq,
+        // softirq, steal, and then guest time (which is already
+        // counted in user, so we don't include it in the total)
+        let idle = fields[3] + fields.get(4).unwrap_or(&0);
+        let total = fields.iter().take(8).sum();
+        samples.push(Sample { idle, total });
+    }
+    if samples.is_empty() {
+        bail!("No CPU lines found in {}", path.display());
+    }
+    Ok(samples)
+}
+
+/// The usage for each individual core, leaving out the aggregate
+fn per_core(usage: &[f64]) -> &[f64] {
+    usage.get(1..).unwrap_or(&[])
+}
+
+fn set_usage_color(d: &Drawing, amt: f64) {
+    match amt {
+        x if x > 0.9 => d.ctx.set_source_rgb(1.0, 0.0, 0.0),
+        x if x > 0.5 => d.ctx.set_source_rgb(1.0, 1.0, 0.0),
+        _ => d.ctx.set_source_rgb(0.0, 1.0, 0.5),
+    }
+}
+
+impl Widget for Cpu {
+    fn draw(&self, d: &Drawing, loc: Located) -> i32 {
+        let readings = self.readings.lock().unwrap();
+        match self.mode {
+            Mode::Aggregate => {
+                let amt = readings.usage.first().cloned().unwrap_or(0.0);
+                loc.draw_text(d, &format!("cpu {:>3.0}%", amt * 100.0))
+            }
+            Mode::PerCore => {
+                let cores = per_core(&readings.usage);
+                // a `/proc/stat` without any `cpuN` lines would leave
+                // us with nothing to draw, so say so instead
+                if cores.is_empty() {
+                    return loc.draw_text(d, "cpu: no per-core stats");
+                }
+                let sz = d.size.ht - (d.buffer as i32 * 2);
+                let bar_wd = (sz / 3).max(3);
+                let wd = (bar_wd + 1) * cores.len() as i32 - 1;
+                let x = loc.target_x(d, wd);
+                let top = d.buffer * 2.0;
+                let ht = sz as f64 - d.buffer * 2.0;
+
+                for (i, amt) in cores.iter().enumerate() {
+                    let bar_x = x + ((bar_wd + 1) * i as i32) as f64;
+                    set_usage_color(d, *amt);
+                    d.ctx
+                        .rectangle(bar_x, top + ht * (1.0 - amt), bar_wd as f64, ht * amt);
+                    d.ctx.fill();
+
+                    d.ctx.set_source_rgb(1.0, 1.0, 1.0);
+                    d.ctx.rectangle(bar_x, top, bar_wd as f64, ht);
+                    d.ctx.stroke();
+                }
+
+                wd
+            }
+        }
+    }
+
+    fn update_frequency(&self) -> Option<Frequency> {
+        Some(Frequency::Every(self.interval))
+    }
+
+    fn update_blocks(&self) -> bool {
+        true
+    }
+
+    fn update(&self) {
+        let samples = match read_samples(&self.stat_path) {
+            Ok(samples) => samples,
+            Err(err) => {
+                eprintln!("Failed to read CPU usage: {}", err);
+                return;
+            }
+        };
+
+        let mut readings = self.readings.lock().unwrap();
+        readings.usage = samples
+            .iter()
+            .enumerate()
+            .map(|(i, now)| {
+                let last = readings.last_samples.get(i).cloned().unwrap_or_default();
+                let total = now.total.saturating_sub(last.total);
+                let idle = now.idle.saturating_sub(last.idle);
+                if total == 0 {
+                    0.0
+                } else {
+                    1.0 - (idle as f64 / total as f64)
+                }
+            })
+            .collect();
+        readings.last_samples = samples;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// A directory just for this test, with `stat` in it
+    fn proc_root(name: &str, stat: &str) -> TempDir {
+        let dir = TempDir::new(&format!("cpu-{}", name));
+        dir.write("stat", stat);
+        dir
+    }
+
+    #[test]
+    fn no_per_core_lines() {
+        let root = proc_root("aggregate-only", "cpu  10 0 10 80 0 0 0 0 0 0\nintr 1\n");
+        let cpu = Cpu::new(root.path(), Mode::PerCore, Duration::from_secs(1)).unwrap();
+        cpu.update();
+        let readings = cpu.readings.lock().unwrap();
+        assert_eq!(readings.usage.len(), 1);
+        assert!(per_core(&readings.usage).is_empty());
+    }
+
+    #[test]
+    fn usage_between_samples() {
+        let root = proc_root(
+            "usage",
+            "cpu  10 0 10 80 0 0 0 0 0 0\ncpu0 5 0 5 40\ncpu1 5 0 5 40\n",
+        );
+        let cpu = Cpu::new(root.path(), Mode::PerCore, Duration::from_secs(1)).unwrap();
+        root.write(
+            "stat",
+            "cpu  60 0 10 130 0 0 0 0 0 0\ncpu0 55 0 5 40\ncpu1 5 0 5 90\n",
+        );
+        cpu.update();
+        let readings = cpu.readings.lock().unwrap();
+        assert_eq!(readings.usage, vec![0.5, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn no_cpu_lines_at_all() {
+        let root = proc_root("empty", "intr 1\n");
+        assert!(Cpu::new(root.path(), Mode::Aggregate, Duration::from_secs(1)).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use crate::widgets::widget::{Drawing, Frequency, Located, Widget};
+
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a child gets to exit by itself before we move on to
+/// asking it to, and then to making it
+const GRACE_PERIOD: Duration = Duration::from_millis(200);
+
+/// A long-running child process whose every line of output becomes
+/// our new text, in the style of i3blocks' persistent mode
+pub struct Exec {
+    process: Mutex<Process>,
+}
+
+/// A long-running child process, which gets restarted (with a
+/// backoff) if it ever goes away
+pub struct Process {
+    command: String,
+    // whether the child gets a pipe on its stdin, for widgets that
+    // have things to tell it
+    pipe_stdin: bool,
+    child: Option<Child>,
+    // whatever's been read since the last newline
+    partial: Vec<u8>,
+    // the first line the child printed, which some protocols use as
+    // a header
+    first_line: Option<String>,
+    text: String,
+    started: Instant,
+    restart_at: Instant,
+    backoff: Duration,
+}
+
+impl Exec {
+    pub fn new(command: String) -> Result<Exec, failure::Error> {
+        Ok(Exec {
+            process: Mutex::new(Process::new(command, false)),
+        })
+    }
+}
+
+impl Process {
+    /// This doesn't start the child yet: that happens on the first
+    /// `restart_if_due`, so that just reading a config (e.g. for
+    /// `knurling --check`) doesn't go running the user's commands
+    pub fn new(command: String, pipe_stdin: bool) -> Process {
+        Process {
+            command,
+            pipe_stdin,
+            child: None,
+            partial: Vec::new(),
+            first_line: None,
+            text: "...".to_string(),
+            started: Instant::now(),
+            restart_at: Instant::now(),
+            backoff: MIN_BACKOFF,
+        }
+    }
+
+    /// The most recent complete line the child printed
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn first_line(&self) -> Option<&str> {
+        self.first_line.as_deref()
+    }
+
+    /// When the child currently running got started, which is also a
+    /// handy way of telling whether it's been restarted
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    fn spawn(&mut self) -> Result<(), failure::Error> {
+        let stdin = if self.pipe_stdin {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        };
+        let child = std::process::Command::new("/bin/sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .spawn()?;
+
+        // we only ever read from this when select tells us there's
+        // something there, but a partial line shouldn't block us
+        // waiting for the rest of it. Likewise, a child that isn't
+        // reading its stdin shouldn't be able to hold us up.
+        let fds = child.stdout.as_ref().map(|s| s.as_raw_fd()).into_iter();
+        for fd in fds.chain(child.stdin.as_ref().map(|s| s.as_raw_fd())) {
+            unsafe {
+                let flags = libc::fcntl(fd, libc::F_GETFL);
+                libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+            }
+        }
+
+        self.child = Some(child);
+        self.partial.clear();
+        self.first_line = None;
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    /// Start the child up again if it's gone away and it's been long
+    /// enough since the last time
+    pub fn restart_if_due(&mut self) {
+        if self.child.is_none() && Instant::now() >= self.restart_at {
+            if let Err(err) = self.spawn() {
+                eprintln!("Unable to start {:?}: {}", self.command, err);
+                self.reap();
+            }
+        }
+    }
+
+    /// Clean up after a child that's gone away, and figure out when
+    /// we should try starting it again
+    fn reap(&mut self) {
+        let command = &self.command;
+        if let Some(mut child) = self.child.take() {
+            // closing its stdout usually means it's exiting, but it
+            // might carry on running anyway, and we can't sit here
+            // waiting on it forever
+            let status = match wait_briefly(&mut child) {
+                Ok(Some(status)) => Ok(status),
+                _ => stop(&mut child),
+            };
+            match status {
+                Ok(status) => eprintln!("Command {:?} exited: {}", command, status),
+                Err(err) => eprintln!("Command {:?} failed: {}", command, err),
+            }
+        }
+
+        // if it stayed up for a good while, then this probably isn't
+        // a crash loop, so we can go back to restarting it promptly
+        if self.started.elapsed() > MAX_BACKOFF {
+            self.backoff = MIN_BACKOFF;
+        }
+        self.restart_at = Instant::now() + self.backoff;
+        self.backoff = std::cmp::min(self.backoff * 2, MAX_BACKOFF);
+    }
+
+    pub fn fd(&self) -> Option<RawFd> {
+        self.child
+            .as_ref()
+            .and_then(|c| c.stdout.as_ref())
+            .map(|s| s.as_raw_fd())
+    }
+
+    /// Send something to the child. If it's not keeping up with what
+    /// we're sending, then this gets dropped rather than waiting.
+    pub fn write(&mut self, data: &[u8]) {
+        use std::io::Write;
+        if let Some(stdin) = self.child.as_mut().and_then(|c| c.stdin.as_mut()) {
+            if let Err(err) = stdin.write_all(data) {
+                eprintln!("Error writing to {:?}: {}", self.command, err);
+            }
+        }
+    }
+
+    pub fn read(&mut self) {
+        let mut buf = [0; 4096];
+        let mut finished = false;
+        while let Some(stdout) = self.child.as_mut().and_then(|c| c.stdout.as_mut()) {
+            match stdout.read(&mut buf) {
+                Ok(0) => {
+                    finished = true;
+                    break;
+                }
+                Ok(n) => self.partial.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(err) => {
+                    eprintln!("Error reading from {:?}: {}", self.command, err);
+                    finished = true;
+                    break;
+                }
+            }
+        }
+
+        // if the child's gone, then whatever it left without a
+        // trailing newline is still the last thing it said
+        if finished && !self.partial.is_empty() && !self.partial.ends_with(b"\n") {
+            self.partial.push(b'\n');
+        }
+
+        // we only care about the most recent complete line
+        if let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') {
+            let lines: Vec<u8> = self.partial.drain(..=end).collect();
+            let lines = String::from_utf8_lossy(&lines[..end]);
+            if self.first_line.is_none() {
+                self.first_line = lines.lines().next().map(|l| l.to_string());
+            }
+            if let Some(line) = lines.lines().next_back() {
+                self.text = line.to_string();
+            }
+        }
+
+        if finished {
+            self.reap();
+        }
+    }
+}
+
+/// Wait for `child` to exit, but only for so long
+fn wait_briefly(child: &mut Child) -> std::io::Result<Option<ExitStatus>> {
+    let give_up = Instant::now() + GRACE_PERIOD;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= give_up {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Ask `child` and everything else in its process group to exit, and
+/// if that doesn't do it then kill them, so that this never holds us
+/// up for long no matter what the child does with its signals
+fn stop(child: &mut Child) -> std::io::Result<ExitStatus> {
+    let group = -(child.id() as i32);
+    unsafe {
+        libc::kill(group, libc::SIGTERM);
+    }
+    if let Some(status) = wait_briefly(child)? {
+        return Ok(status);
+    }
+    unsafe {
+        libc::kill(group, libc::SIGKILL);
+    }
+    child.wait()
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if let Some(ref mut child) = self.child {
+            let _ = stop(child);
+        }
+    }
+}
+
+impl Widget for Exec {
+    fn draw(&self, d: &Drawing, loc: Located) -> i32 {
+        loc.draw_text(d, self.process.lock().unwrap().text())
+    }
+
+    fn update_frequency(&self) -> Option<Frequency> {
+        Some(Frequency::seconds(1))
+    }
+
+    fn update(&self) {
+        self.process.lock().unwrap().restart_if_due();
+    }
+
+    fn fd(&self) -> Option<RawFd> {
+        self.process.lock().unwrap().fd()
+    }
+
+    fn on_readable(&self) {
+        self.process.lock().unwrap().read();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_runs_until_the_first_update() {
+        let exec = Exec::new("cat".to_string()).unwrap();
+        assert!(exec.fd().is_none());
+        exec.update();
+        assert!(exec.fd().is_some());
+    }
+
+    /// Start `command` up, returning it along with the process group
+    /// it's in
+    fn started(command: &str) -> (Process, i32) {
+        let mut process = Process::new(command.to_string(), false);
+        process.restart_if_due();
+        let group = process.child.as_ref().unwrap().id() as i32;
+        (process, group)
+    }
+
+    /// Whether anything in `group` is still running. Anything the
+    /// child started gets handed off to init once the child's gone,
+    /// so that's who waits for those, and they might still be zombies
+    /// by the time we look.
+    fn alive(group: i32) -> bool {
+        let procs = std::fs::read_dir("/proc").unwrap();
+        procs.filter_map(|p| p.ok()).any(|p| {
+            let stat = std::fs::read_to_string(p.path().join("stat")).unwrap_or_default();
+            // this is `pid (comm) state ppid pgrp ...`, where `comm`
+            // could have anything in it
+            let fields: Vec<&str> = match stat.rfind(')') {
+                Some(end) => stat[end + 1..].split_whitespace().collect(),
+                None => return false,
+            };
+            fields.get(2) == Some(&group.to_string().as_str()) && fields.first() != Some(&"Z")
+        })
+    }
+
+    #[test]
+    fn stopping_a_child_that_ignores_sigterm() {
+        let (process, group) = started("trap '' TERM; sleep 10; sleep 10");
+        assert!(alive(group));
+        let start = Instant::now();
+        drop(process);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(!alive(group));
+    }
+
+    #[test]
+    fn a_child_that_closes_stdout_but_keeps_running() {
+        let (mut process, group) = started("echo hello; exec >&-; sleep 10");
+        let start = Instant::now();
+        while process.fd().is_some() && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            process.read();
+        }
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(process.text(), "hello");
+        assert!(process.fd().is_none());
+        assert!(!alive(group));
+    }
+}
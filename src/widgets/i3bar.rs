@@ -0,0 +1,403 @@
+use crate::config::color_from_hex;
+use crate::widgets::exec::Process;
+use crate::widgets::widget::{Button, Drawing, Frequency, Located, Widget};
+
+use serde::Deserialize;
+use std::os::unix::io::RawFd;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The gap i3bar leaves after a block when the block doesn't say
+const DEFAULT_SEPARATOR_WIDTH: i32 = 9;
+/// The color i3bar draws separators in by default
+const SEPARATOR_COLOR: (f64, f64, f64) = (0.4, 0.4, 0.4);
+
+/// The first thing a program speaking the i3bar protocol prints
+#[derive(Deserialize)]
+struct Header {
+    #[allow(dead_code)]
+    version: i64,
+    #[serde(default)]
+    click_events: bool,
+}
+
+/// One of the blocks in a status line. There are plenty more fields
+/// than this in the protocol, but these are the ones we draw.
+#[derive(Debug, Clone, Deserialize)]
+struct Block {
+    full_text: String,
+    color: Option<String>,
+    background: Option<String>,
+    separator: Option<bool>,
+    separator_block_width: Option<i32>,
+    min_width: Option<MinWidth>,
+    align: Option<Align>,
+    markup: Option<String>,
+    // these don't get drawn, but they get sent back with clicks so
+    // that the program knows which block was clicked on
+    name: Option<String>,
+    instance: Option<String>,
+}
+
+/// The narrowest a block can be, either as a number of pixels or as
+/// some text whose width it should take up
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum MinWidth {
+    Pixels(i32),
+    Text(String),
+}
+
+/// Where text goes in a block that's wider than the text is
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Parse one line of the protocol's infinite array, which (apart from
+/// the opening `[`) is an array of blocks with a comma to separate it
+/// from the others. Most programs put the comma in front of every line
+/// but the first, but some put it after every line instead.
+fn parse_blocks(line: &str) -> Option<Vec<Block>> {
+    let line = line.trim().trim_matches(',').trim();
+    serde_json::from_str(line).ok()
+}
+
+/// i3bar colors are `#RRGGBB` or `#RRGGBBAA`, and we don't do
+/// transparency
+fn color(color: &str) -> Option<(f64, f64, f64)> {
+    color_from_hex(color.get(..7).unwrap_or(color)).ok()
+}
+
+/// Status lines from something that speaks the i3bar protocol, like
+/// i3status or i3blocks. This either runs the program itself, in
+/// which case clicks get sent back to it, or reads it from our stdin.
+///
+/// All of the blocks get drawn by this one widget, rather than each
+/// being a widget of its own, so they share its place in the bar and
+/// its options: a `max_width` squeezes them all together, and the
+/// widget's own `on_click_*` commands run for clicks on any of them.
+pub struct I3bar {
+    process: Option<Mutex<Process>>,
+    state: Mutex<State>,
+}
+
+struct State {
+    // the line we last parsed, so we don't have to do it again until
+    // it changes
+    line: String,
+    blocks: Vec<Block>,
+    // where each block got drawn, relative to the start of the
+    // widget, and how tall the bar was
+    regions: Vec<(f64, f64)>,
+    height: i32,
+    // where the start of the widget is on the root window, since
+    // that's what click events are measured from
+    origin: (f64, f64),
+    // which run of the program we've started sending clicks to, since
+    // the first click has to start the array off
+    clicks_since: Option<Instant>,
+}
+
+impl I3bar {
+    pub fn new(command: Option<String>) -> Result<I3bar, failure::Error> {
+        let process = command.map(|command| Mutex::new(Process::new(command, true)));
+        Ok(I3bar {
+            process,
+            state: Mutex::new(State {
+                line: String::new(),
+                blocks: Vec::new(),
+                regions: Vec::new(),
+                height: 0,
+                origin: (0.0, 0.0),
+                clicks_since: None,
+            }),
+        })
+    }
+}
+
+/// The JSON to send back for a click on `block`, which is `wd` wide
+/// and starts `start` pixels into the widget. `x` and `y` are where
+/// the click was in the widget, and `origin` is where the widget is
+/// on the root window: the protocol wants `x` and `y` measured from
+/// the root window, and `relative_x` and `relative_y` from the block.
+fn click_event(
+    block: &Block,
+    button: Button,
+    origin: (f64, f64),
+    (x, y): (f64, f64),
+    (start, wd): (f64, f64),
+    height: i32,
+) -> serde_json::Value {
+    serde_json::json!({
+        "name": block.name,
+        "instance": block.instance,
+        "button": button.to_x11(),
+        "modifiers": [],
+        "x": (origin.0 + x) as i32,
+        "y": (origin.1 + y) as i32,
+        "relative_x": (x - start) as i32,
+        "relative_y": y as i32,
+        "width": wd as i32,
+        "height": height,
+    })
+}
+
+/// Measure something by drawing it somewhere that doesn't show
+fn measure(d: &Drawing, text: &str) -> i32 {
+    d.ctx.push_group();
+    let wd = Located::FromLeft(0).draw_text(d, text);
+    d.ctx.pop_group();
+    wd
+}
+
+impl Widget for I3bar {
+    fn draw(&self, d: &Drawing, loc: Located) -> i32 {
+        let line = match self.process {
+            Some(ref process) => process.lock().unwrap().text().to_string(),
+            None => d.stdin.to_string(),
+        };
+        let mut state = self.state.lock().unwrap();
+        if state.line != line {
+            // the header, the opening `[`, and anything else we can't
+            // make sense of leave the last blocks up
+            if let Some(blocks) = parse_blocks(&line) {
+                state.blocks = blocks;
+            }
+            state.line = line;
+        }
+
+        // figure out how wide everything is before drawing any of it,
+        // since we might be drawing from the right
+        let plain = &Drawing {
+            limit: None,
+            markup: false,
+            ..*d
+        };
+        let mut layout = Vec::new();
+        for block in state.blocks.iter() {
+            let d = &Drawing {
+                markup: block.markup.as_deref() == Some("pango"),
+                ..*plain
+            };
+            let text_wd = measure(d, &block.full_text);
+            let min_wd = match block.min_width {
+                Some(MinWidth::Pixels(n)) => n,
+                Some(MinWidth::Text(ref text)) => measure(plain, text),
+                None => 0,
+            };
+            let gap = block
+                .separator_block_width
+                .unwrap_or(DEFAULT_SEPARATOR_WIDTH);
+            layout.push((text_wd, text_wd.max(min_wd), gap));
+        }
+        // there's no gap after the last block
+        if let Some(last) = layout.last_mut() {
+            last.2 = 0;
+        }
+        let natural: i32 = layout.iter().map(|(_, wd, gap)| wd + gap).sum();
+        let total = d.limit.map_or(natural, |limit| natural.min(limit));
+        let start = loc.target_x(d, total);
+
+        d.ctx.save();
+        d.ctx.rectangle(start, 0.0, total as f64, d.size.ht as f64);
+        d.ctx.clip();
+        let mut regions = Vec::new();
+        let mut x = start;
+        for (block, &(text_wd, wd, gap)) in state.blocks.iter().zip(layout.iter()) {
+            d.ctx.save();
+            if let Some((r, g, b)) = block.background.as_deref().and_then(color) {
+                d.ctx.set_source_rgb(r, g, b);
+                d.ctx.rectangle(x, 0.0, wd as f64, d.size.ht as f64);
+                d.ctx.fill();
+            }
+            if let Some((r, g, b)) = block.color.as_deref().and_then(color) {
+                d.ctx.set_source_rgb(r, g, b);
+            }
+            let offset = match block.align.unwrap_or(Align::Left) {
+                Align::Left => 0,
+                Align::Center => (wd - text_wd) / 2,
+                Align::Right => wd - text_wd,
+            };
+            let text = &Drawing {
+                markup: block.markup.as_deref() == Some("pango"),
+                ..*plain
+            };
+            Located::FromLeft(x as i32 + offset).draw_text(text, &block.full_text);
+            d.ctx.restore();
+
+            if block.separator.unwrap_or(true) && gap > 0 {
+                let (r, g, b) = SEPARATOR_COLOR;
+                let sep_x = (x + wd as f64 + gap as f64 / 2.0).floor() + 0.5;
+                d.ctx.save();
+                d.ctx.set_source_rgb(r, g, b);
+                d.ctx.set_line_width(1.0);
+                d.ctx.move_to(sep_x, d.buffer);
+                d.ctx.line_to(sep_x, d.size.ht as f64 - d.buffer);
+                d.ctx.stroke();
+                d.ctx.restore();
+            }
+
+            regions.push((x - start, wd as f64));
+            x += (wd + gap) as f64;
+        }
+        d.ctx.restore();
+
+        state.regions = regions;
+        state.height = d.size.ht;
+        state.origin = (f64::from(d.size.xo) + start, f64::from(d.size.yo));
+        total
+    }
+
+    fn update_frequency(&self) -> Option<Frequency> {
+        // this is just for restarting the program if it goes away
+        self.process.as_ref().map(|_| Frequency::seconds(1))
+    }
+
+    fn update(&self) {
+        if let Some(ref process) = self.process {
+            process.lock().unwrap().restart_if_due();
+        }
+    }
+
+    fn fd(&self) -> Option<RawFd> {
+        self.process.as_ref().and_then(|p| p.lock().unwrap().fd())
+    }
+
+    fn on_readable(&self) {
+        if let Some(ref process) = self.process {
+            process.lock().unwrap().read();
+        }
+    }
+
+    fn on_click(&self, button: Button, x: f64, y: f64) {
+        let mut process = match self.process {
+            Some(ref process) => process.lock().unwrap(),
+            None => return,
+        };
+        let wants_clicks = process
+            .first_line()
+            .and_then(|l| serde_json::from_str::<Header>(l).ok())
+            .is_some_and(|h| h.click_events);
+        if !wants_clicks {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let clicked = state
+            .regions
+            .iter()
+            .zip(state.blocks.iter())
+            .find(|((start, wd), _)| x >= *start && x < start + wd);
+        let ((start, wd), block) = match clicked {
+            Some(clicked) => clicked,
+            None => return,
+        };
+        let event = click_event(
+            block,
+            button,
+            state.origin,
+            (x, y),
+            (*start, *wd),
+            state.height,
+        );
+
+        // clicks are an infinite array of their own, so the first
+        // one needs to open it and the rest need separating
+        let started = process.started();
+        let prefix = if state.clicks_since == Some(started) {
+            ","
+        } else {
+            "[\n"
+        };
+        state.clicks_since = Some(started);
+        process.write(format!("{}{}\n", prefix, event).as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_lines() {
+        // the first line of blocks doesn't have a comma in front, but
+        // all the ones after it do...
+        let first = parse_blocks(r#"[{"full_text": "a", "name": "disk"}]"#).unwrap();
+        let later = parse_blocks(r#" ,[{"full_text": "b"}, {"full_text": "c"}]"#).unwrap();
+        assert_eq!(first[0].full_text, "a");
+        assert_eq!(first[0].name.as_deref(), Some("disk"));
+        assert_eq!(later.len(), 2);
+        assert_eq!(later[1].full_text, "c");
+
+        // ...unless the commas come at the end instead
+        let trailing = parse_blocks(r#"[{"full_text": "d"}], "#).unwrap();
+        assert_eq!(trailing[0].full_text, "d");
+
+        // neither the start of the infinite array nor the header is
+        // a line of blocks
+        assert!(parse_blocks("[").is_none());
+        assert!(parse_blocks(r#"{"version": 1}"#).is_none());
+    }
+
+    #[test]
+    fn min_widths() {
+        let blocks = parse_blocks(
+            r#"[{"full_text": "a", "min_width": 50}, {"full_text": "b", "min_width": "100%"}]"#,
+        )
+        .unwrap();
+        assert!(matches!(blocks[0].min_width, Some(MinWidth::Pixels(50))));
+        assert!(matches!(blocks[1].min_width, Some(MinWidth::Text(ref t)) if t == "100%"));
+    }
+
+    #[test]
+    fn colors_lose_their_alpha() {
+        assert_eq!(color("#ff000080"), color("#ff0000"));
+        assert_eq!(color("#ff0000"), Some((1.0, 0.0, 0.0)));
+        assert_eq!(color("red"), None);
+    }
+
+    #[test]
+    fn headers() {
+        let header: Header =
+            serde_json::from_str(r#"{"version": 1, "click_events": true}"#).unwrap();
+        assert!(header.click_events);
+        let header: Header = serde_json::from_str(r#"{"version": 1}"#).unwrap();
+        assert!(!header.click_events);
+    }
+
+    #[test]
+    fn click_coordinates() {
+        let block = parse_blocks(r#"[{"full_text": "a", "name": "vol", "instance": "0"}]"#)
+            .unwrap()
+            .remove(0);
+        // a bar at (1920, 30) on the root window, with the widget 100
+        // pixels into it and the block 40 pixels into the widget
+        let event = click_event(
+            &block,
+            Button::Left,
+            (2020.0, 30.0),
+            (55.0, 7.0),
+            (40.0, 30.0),
+            24,
+        );
+        assert_eq!(
+            event,
+            serde_json::json!({
+                "name": "vol",
+                "instance": "0",
+                "button": 1,
+                "modifiers": [],
+                "x": 2075,
+                "y": 37,
+                "relative_x": 15,
+                "relative_y": 7,
+                "width": 30,
+                "height": 24,
+            })
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::config::Section;
+use crate::widgets::styled::{push_run, valid_color, Action, Content, Run};
+use crate::widgets::widget::Button;
+
+/// Parse a line of lemonbar-style formatting, e.g.
+/// `%{l}%{F#f00}left%{F-}%{r}%{A:cmd:}right%{A}`, and return the runs
+/// that belong in the `align` part of the bar. Anything before the
+/// first alignment tag goes on the left, just like in lemonbar.
+pub fn parse(input: &str, align: Section) -> Vec<Run> {
+    let mut state = State {
+        align: Section::Left,
+        fg: None,
+        bg: None,
+        underline: false,
+        underline_color: None,
+        actions: Vec::new(),
+    };
+    let mut runs = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('%') {
+            if let Some(len) = after
+                .strip_prefix('{')
+                .and_then(|b| state.block(b, align, &mut runs))
+            {
+                rest = &after[1 + len..];
+                continue;
+            }
+            // a `%` that isn't starting a block just means the next
+            // character is taken literally, so `%%` is a percent sign
+            let len = match after.chars().next() {
+                Some(c) => c.len_utf8(),
+                None => break,
+            };
+            state.push(&mut runs, align, Content::Text(after[..len].to_string()));
+            rest = &after[len..];
+        } else {
+            let end = rest.find('%').unwrap_or(rest.len());
+            state.push(&mut runs, align, Content::Text(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+    }
+    runs
+}
+
+struct State {
+    align: Section,
+    fg: Option<String>,
+    bg: Option<String>,
+    underline: bool,
+    underline_color: Option<String>,
+    actions: Vec<Action>,
+}
+
+impl State {
+    fn push(&self, runs: &mut Vec<Run>, align: Section, content: Content) {
+        if self.align != align {
+            return;
+        }
+        push_run(
+            runs,
+            Run {
+                content,
+                fg: self.fg.clone(),
+                bg: self.bg.clone(),
+                underline: self.underline,
+                underline_color: self.underline_color.clone(),
+                actions: self.actions.clone(),
+            },
+        );
+    }
+
+    /// Apply the commands in a `%{...}` block, where `body` is
+    /// everything after the opening brace. This returns how much of
+    /// `body` the block took up, or `None` if it never got closed.
+    fn block(&mut self, body: &str, align: Section, runs: &mut Vec<Run>) -> Option<usize> {
+        // the block has to be closed for any of it to count, so check
+        // that before changing anything
+        body.find('}')?;
+        let mut pos = 0;
+        loop {
+            let rest = &body[pos..];
+            let trimmed = rest.trim_start();
+            pos += rest.len() - trimmed.len();
+            let mut chars = trimmed.chars();
+            let cmd = chars.next()?;
+            pos += cmd.len_utf8();
+            if cmd == '}' {
+                return Some(pos);
+            }
+            if cmd == 'A' {
+                pos += self.action(&body[pos..])?;
+                continue;
+            }
+
+            // everything else takes an argument that runs up until the
+            // next space or the end of the block
+            let arg_len = body[pos..]
+                .find(|c: char| c == '}' || c.is_whitespace())
+                .unwrap_or(body.len() - pos);
+            let arg = &body[pos..pos + arg_len];
+            pos += arg_len;
+            match (cmd, arg) {
+                ('l', _) => self.align = Section::Left,
+                ('c', _) => self.align = Section::Center,
+                ('r', _) => self.align = Section::Right,
+                ('R', _) => std::mem::swap(&mut self.fg, &mut self.bg),
+                ('F', _) => self.fg = color(arg),
+                ('B', _) => self.bg = color(arg),
+                ('U', _) => self.underline_color = color(arg),
+                ('O', _) => {
+                    if let Ok(wd) = arg.parse() {
+                        self.push(runs, align, Content::Space(wd));
+                    }
+                }
+                ('+', "u") => self.underline = true,
+                ('-', "u") => self.underline = false,
+                ('!', "u") => self.underline = !self.underline,
+                // fonts, overlines and picking a monitor are all
+                // things we don't do, so they just get skipped over
+                _ => (),
+            }
+        }
+    }
+
+    /// Handle an `A` command, which is either the start of a
+    /// clickable area like `A3:command:` or, without the command, the
+    /// end of one. This returns how much of `body` it took up.
+    fn action(&mut self, body: &str) -> Option<usize> {
+        let digits = body.find(|c: char| !c.is_ascii_digit())?;
+        let button = match body[..digits].parse() {
+            Ok(n) => Button::from_x11(n),
+            Err(_) => Button::Left,
+        };
+        let command = match body[digits..].strip_prefix(':') {
+            Some(command) => command,
+            None => {
+                self.actions.pop();
+                return Some(digits);
+            }
+        };
+
+        // the command runs up to the next `:` that hasn't been
+        // escaped with a backslash
+        let mut end = None;
+        let mut escaped = false;
+        for (i, c) in command.char_indices() {
+            match c {
+                ':' if !escaped => {
+                    end = Some(i);
+                    break;
+                }
+                '\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+        }
+        let end = end?;
+        self.actions.push(Action {
+            command: command[..end].replace("\\:", ":"),
+            buttons: vec![button],
+        });
+        Some(digits + 1 + end + 1)
+    }
+}
+
+/// Turn a lemonbar color into one Pango understands. Lemonbar allows
+/// `#AARRGGBB`, but we don't do transparency, so the alpha gets
+/// dropped. A `-` (or anything we can't make sense of) goes back to
+/// the default.
+fn color(arg: &str) -> Option<String> {
+    let arg = match arg.strip_prefix('#') {
+        Some(hex) if hex.len() == 8 && hex.is_ascii() => format!("#{}", &hex[2..]),
+        _ => arg.to_string(),
+    };
+    if arg != "-" && valid_color(&arg) {
+        Some(arg)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Run {
+        Run {
+            content: Content::Text(s.to_string()),
+            fg: None,
+            bg: None,
+            underline: false,
+            underline_color: None,
+            actions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn alignment() {
+        let line = "start%{c}middle%{r}end%{l} more";
+        assert_eq!(parse(line, Section::Left), vec![text("start more")]);
+        assert_eq!(parse(line, Section::Center), vec![text("middle")]);
+        assert_eq!(parse(line, Section::Right), vec![text("end")]);
+    }
+
+    #[test]
+    fn colors_and_underlines() {
+        assert_eq!(
+            parse(
+                "%{F#80ff0000 B#00ff00}a%{R}b%{F- B-}c%{+u U#0000ff}d%{-u}",
+                Section::Left
+            ),
+            vec![
+                Run {
+                    fg: Some("#ff0000".to_string()),
+                    bg: Some("#00ff00".to_string()),
+                    ..text("a")
+                },
+                Run {
+                    fg: Some("#00ff00".to_string()),
+                    bg: Some("#ff0000".to_string()),
+                    ..text("b")
+                },
+                text("c"),
+                Run {
+                    underline: true,
+                    underline_color: Some("#0000ff".to_string()),
+                    ..text("d")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(
+            parse("%{A3:echo a\\:b:}x%{A}y", Section::Left),
+            vec![
+                Run {
+                    actions: vec![Action {
+                        command: "echo a:b".to_string(),
+                        buttons: vec![Button::Right],
+                    }],
+                    ..text("x")
+                },
+                text("y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(
+            parse("100%% %{O10}", Section::Left),
+            vec![
+                text("100% "),
+                Run {
+                    content: Content::Space(10),
+                    ..text("")
+                },
+            ]
+        );
+        // commands we don't do just get skipped
+        assert_eq!(parse("%{T2 o#fff}x", Section::Left), vec![text("x")]);
+    }
+}
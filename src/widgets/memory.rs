@@ -0,0 +1,255 @@
+use crate::widgets::template::Template;
+use crate::widgets::widget::{Drawing, Frequency, Located, Widget};
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const DEFAULT_FORMAT: &str = "mem {used_gib}/{total_gib}";
+
+/// The values, in kibibytes, that we care about from `/proc/meminfo`
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    total: u64,
+    used: u64,
+    swap_total: u64,
+    swap_used: u64,
+}
+
+impl Usage {
+    fn fraction(used: u64, total: u64) -> f64 {
+        if total == 0 {
+            0.0
+        } else {
+            used as f64 / total as f64
+        }
+    }
+
+    const KEYS: &'static [&'static str] = &[
+        "used_gib",
+        "total_gib",
+        "used_mib",
+        "total_mib",
+        "used_percent",
+        "swap_used_gib",
+        "swap_total_gib",
+        "swap_used_mib",
+        "swap_total_mib",
+        "swap_used_percent",
+    ];
+
+    fn lookup(&self, key: &str) -> String {
+        let gib = |kib: u64| format!("{:.1}", kib as f64 / (1024.0 * 1024.0));
+        let mib = |kib: u64| format!("{}", kib / 1024);
+        let percent = |used, total| format!("{:.0}", Usage::fraction(used, total) * 100.0);
+        match key {
+            "used_gib" => gib(self.used),
+            "total_gib" => gib(self.total),
+            "used_mib" => mib(self.used),
+            "total_mib" => mib(self.total),
+            "used_percent" => percent(self.used, self.total),
+            "swap_used_gib" => gib(self.swap_used),
+            "swap_total_gib" => gib(self.swap_total),
+            "swap_used_mib" => mib(self.swap_used),
+            "swap_total_mib" => mib(self.swap_total),
+            "swap_used_percent" => percent(self.swap_used, self.swap_total),
+            _ => String::new(),
+        }
+    }
+}
+
+pub enum Style {
+    Text(Template),
+    Gauge,
+}
+
+impl Style {
+    pub fn text(template: &str) -> Result<Style, failure::Error> {
+        Ok(Style::Text(Template::parse(
+            "format",
+            template,
+            Usage::KEYS,
+        )?))
+    }
+}
+
+pub struct Memory {
+    meminfo_path: PathBuf,
+    style: Style,
+    threshold: f64,
+    interval: Duration,
+    last_usage: Mutex<Usage>,
+}
+
+impl Memory {
+    pub fn new(
+        proc_root: &Path,
+        style: Style,
+        threshold: f64,
+        interval: Duration,
+    ) -> Result<Memory, failure::Error> {
+        let meminfo_path = proc_root.join("meminfo");
+        let last_usage = read_usage(&meminfo_path)?;
+        Ok(Memory {
+            meminfo_path,
+            style,
+            threshold,
+            interval,
+            last_usage: Mutex::new(last_usage),
+        })
+    }
+
+    fn draw_gauge(&self, d: &Drawing, x: f64, sz: i32, amt: f64) {
+        let (r, g, b) = gauge_color(amt, self.threshold);
+        d.ctx.set_source_rgb(r, g, b);
+        d.ctx.rectangle(
+            x,
+            d.buffer * 2.0,
+            sz as f64 * amt,
+            sz as f64 - d.buffer * 2.0,
+        );
+        d.ctx.fill();
+
+        d.ctx.set_source_rgb(1.0, 1.0, 1.0);
+        d.ctx
+            .rectangle(x, d.buffer * 2.0, sz as f64, sz as f64 - (d.buffer * 2.0));
+        d.ctx.stroke();
+    }
+}
+
+/// Gauges go red once they're past the threshold
+fn gauge_color(amt: f64, threshold: f64) -> (f64, f64, f64) {
+    if amt > threshold {
+        (1.0, 0.0, 0.0)
+    } else {
+        (0.0, 1.0, 0.5)
+    }
+}
+
+fn read_usage(path: &Path) -> Result<Usage, failure::Error> {
+    let body = std::fs::read_to_string(path)?;
+    let mut total = None;
+    let mut available = None;
+    let mut swap_total = 0;
+    let mut swap_free = 0;
+    for line in body.lines() {
+        let mut parts = line.split_whitespace();
+        let (key, val) = match (parts.next(), parts.next()) {
+            (Some(key), Some(val)) => (key, val),
+            _ => continue,
+        };
+        match key {
+            "MemTotal:" => total = Some(val.parse()?),
+            "MemAvailable:" => available = Some(val.parse()?),
+            "SwapTotal:" => swap_total = val.parse()?,
+            "SwapFree:" => swap_free = val.parse()?,
+            _ => (),
+        }
+    }
+
+    // this is how `free` computes "used": anything the kernel
+    // couldn't hand out right now without swapping
+    let total: u64 = total.ok_or_else(|| format_err!("No MemTotal in {}", path.display()))?;
+    let available: u64 =
+        available.ok_or_else(|| format_err!("No MemAvailable in {}", path.display()))?;
+    Ok(Usage {
+        total,
+        used: total.saturating_sub(available),
+        swap_total,
+        swap_used: swap_total.saturating_sub(swap_free),
+    })
+}
+
+impl Widget for Memory {
+    fn draw(&self, d: &Drawing, loc: Located) -> i32 {
+        let usage = *self.last_usage.lock().unwrap();
+        match self.style {
+            Style::Text(ref template) => loc.draw_text(d, &template.render(|k| usage.lookup(k))),
+            Style::Gauge => {
+                let sz = d.size.ht - (d.buffer as i32 * 2);
+                // we only bother showing swap if there's any to show
+                let wd = if usage.swap_total > 0 { sz * 2 + 4 } else { sz };
+                let x = loc.target_x(d, wd);
+                self.draw_gauge(d, x, sz, Usage::fraction(usage.used, usage.total));
+                if usage.swap_total > 0 {
+                    let amt = Usage::fraction(usage.swap_used, usage.swap_total);
+                    self.draw_gauge(d, x + (sz + 4) as f64, sz, amt);
+                }
+                wd
+            }
+        }
+    }
+
+    fn update_frequency(&self) -> Option<Frequency> {
+        Some(Frequency::Every(self.interval))
+    }
+
+    fn update_blocks(&self) -> bool {
+        true
+    }
+
+    fn update(&self) {
+        match read_usage(&self.meminfo_path) {
+            Ok(usage) => *self.last_usage.lock().unwrap() = usage,
+            Err(err) => eprintln!("Failed to read memory usage: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const MEMINFO: &str = "MemTotal:        8000000 kB\n\
+                           MemFree:          500000 kB\n\
+                           MemAvailable:    2000000 kB\n\
+                           Buffers:          100000 kB\n\
+                           SwapTotal:       1048576 kB\n\
+                           SwapFree:         524288 kB\n";
+
+    #[test]
+    fn used_is_total_minus_available() {
+        let dir = TempDir::new("meminfo");
+        let usage = read_usage(&dir.write("meminfo", MEMINFO)).unwrap();
+        // not total minus free, which would count the page cache
+        assert_eq!(usage.total, 8_000_000);
+        assert_eq!(usage.used, 6_000_000);
+        assert_eq!(usage.swap_total, 1_048_576);
+        assert_eq!(usage.swap_used, 524_288);
+    }
+
+    #[test]
+    fn missing_available() {
+        let dir = TempDir::new("meminfo-old");
+        let path = dir.write("meminfo", "MemTotal: 8000000 kB\nMemFree: 500000 kB\n");
+        let err = read_usage(&path).unwrap_err();
+        assert!(err.to_string().contains("MemAvailable"), "{}", err);
+    }
+
+    #[test]
+    fn gauge_colors() {
+        assert_eq!(gauge_color(0.89, 0.9), (0.0, 1.0, 0.5));
+        assert_eq!(gauge_color(0.9, 0.9), (0.0, 1.0, 0.5));
+        assert_eq!(gauge_color(0.91, 0.9), (1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rendering() {
+        let dir = TempDir::new("meminfo-render");
+        let usage = read_usage(&dir.write("meminfo", MEMINFO)).unwrap();
+        let template =
+            match Style::text("{used_mib}/{total_mib} ({used_percent}%) swap {swap_used_gib}")
+                .unwrap()
+            {
+                Style::Text(template) => template,
+                Style::Gauge => unreachable!(),
+            };
+        assert_eq!(
+            template.render(|k| usage.lookup(k)),
+            "5859/7812 (75%) swap 0.5"
+        );
+        assert!(Style::text("{used_kib}").is_err());
+        assert!(Style::text("{used_gib").is_err());
+    }
+}
@@ -1,38 +1,156 @@
 pub mod battery;
+pub mod command;
+pub mod cpu;
+pub mod exec;
+pub mod i3bar;
+pub mod lemonbar;
+pub mod memory;
 pub mod mpd;
+pub mod network;
+pub mod options;
 pub mod standard;
+pub mod styled;
+pub mod template;
 pub mod widget;
+pub mod xmobar;
 
-pub use crate::widgets::widget::{Drawing, Located, Size, Widget};
+pub use crate::widgets::options::{KeyError, MaxWidth, Seconds, Section};
+pub use crate::widgets::widget::{Button, Drawing, Frequency, Located, Overflow, Size, Widget};
 
-const ALL_WIDGETS: [(
-    &str,
-    &dyn Fn(&toml::map::Map<String, toml::Value>) -> Result<Box<dyn Widget>, failure::Error>,
-); 6] = [
-    ("box", &|_| Ok(Box::new(standard::Time::new()))),
-    ("battery", &|_| Ok(Box::new(battery::Battery::new()?))),
-    ("caesura", &|_| Ok(Box::new(standard::Caesura))),
-    ("mpd", &|config| {
-        let host = config["host"]
-            .as_str()
-            .ok_or_else(|| format_err!("MPD host should be a string"))?;
-        let port = config["port"]
-            .as_integer()
-            .ok_or_else(|| format_err!("MPD port should be an integer"))?;
-        Ok(Box::new(mpd::MPD::new(host.to_string(), port as usize)))
-    }),
-    ("stdin", &|_| Ok(Box::new(standard::Stdin::new()))),
-    ("time", &|_| Ok(Box::new(standard::Time::new()))),
+use crate::widgets::options as opt;
+use std::time::Duration;
+
+pub type WidgetSection = toml::map::Map<String, toml::Value>;
+type WidgetConstructor = dyn Fn(&mut Section) -> Result<Box<dyn Widget>, failure::Error>;
+
+const ALL_WIDGETS: [(&str, &WidgetConstructor); 13] = [
+    ("box", &|s| {
+        s.parse::<opt::NoOptions>()?;
+        Ok(Box::new(standard::Time::new(None, None, None)?))
+    }),
+    ("battery", &|s| {
+        s.parse::<opt::NoOptions>()?;
+        Ok(Box::new(battery::Battery::new()?))
+    }),
+    ("caesura", &|s| {
+        s.parse::<opt::NoOptions>()?;
+        Ok(Box::new(standard::Caesura))
+    }),
+    ("command", &|s| {
+        let o: opt::CommandOptions = s.parse()?;
+        Ok(Box::new(command::Command::new(
+            o.command,
+            o.interval.map_or(Duration::from_secs(5), |i| i.0),
+            o.timeout.map(|t| t.0),
+            o.line.unwrap_or(command::Line::First),
+            o.error_text
+                .as_deref()
+                .unwrap_or(command::DEFAULT_ERROR_TEXT),
+        )?))
+    }),
+    ("cpu", &|s| {
+        let o: opt::CpuOptions = s.parse()?;
+        Ok(Box::new(cpu::Cpu::new(
+            o.proc_root
+                .as_deref()
+                .unwrap_or_else(|| std::path::Path::new("/proc")),
+            o.mode.unwrap_or(cpu::Mode::Aggregate),
+            o.interval.map_or(Duration::from_secs(2), |i| i.0),
+        )?))
+    }),
+    ("exec", &|s| {
+        let o: opt::ExecOptions = s.parse()?;
+        Ok(Box::new(exec::Exec::new(o.command)?))
+    }),
+    ("i3bar", &|s| {
+        let o: opt::I3barOptions = s.parse()?;
+        Ok(Box::new(i3bar::I3bar::new(o.command)?))
+    }),
+    ("memory", &|s| {
+        let o: opt::MemoryOptions = s.parse()?;
+        let style = match o.style {
+            Some(opt::MemoryStyle::Gauge) => memory::Style::Gauge,
+            Some(opt::MemoryStyle::Text) | None => {
+                memory::Style::text(o.format.as_deref().unwrap_or(memory::DEFAULT_FORMAT))?
+            }
+        };
+        let threshold = o.threshold.unwrap_or(0.9);
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(KeyError {
+                key: Some("threshold".to_string()),
+                message: "should be a number between 0.0 and 1.0".to_string(),
+            }
+            .into());
+        }
+        Ok(Box::new(memory::Memory::new(
+            o.proc_root
+                .as_deref()
+                .unwrap_or_else(|| std::path::Path::new("/proc")),
+            style,
+            threshold,
+            o.interval.map_or(Duration::from_secs(5), |i| i.0),
+        )?))
+    }),
+    ("mpd", &|s| {
+        let o: opt::MpdOptions = s.parse()?;
+        Ok(Box::new(mpd::MPD::new(o.host, o.port as usize)))
+    }),
+    ("network", &|s| {
+        let o: opt::NetworkOptions = s.parse()?;
+        Ok(Box::new(network::Network::new(
+            network::Interface::from_str(o.interface.as_deref().unwrap_or("auto"))?,
+            o.sys_root
+                .as_deref()
+                .unwrap_or_else(|| std::path::Path::new("/sys")),
+            o.proc_root
+                .as_deref()
+                .unwrap_or_else(|| std::path::Path::new("/proc")),
+            o.format.as_deref().unwrap_or(network::DEFAULT_FORMAT),
+            o.interval.map_or(Duration::from_secs(2), |i| i.0),
+        )?))
+    }),
+    ("stdin", &|s| {
+        let o: opt::StdinOptions = s.parse()?;
+        Ok(Box::new(standard::Stdin::new(
+            o.format.unwrap_or(standard::StdinFormat::Plain),
+            o.icon_root,
+        )))
+    }),
+    ("text", &|s| {
+        let o: opt::TextOptions = s.parse()?;
+        Ok(Box::new(standard::Text::new(
+            o.text.as_deref().unwrap_or(""),
+        )))
+    }),
+    ("time", &|s| {
+        let o: opt::TimeOptions = s.parse()?;
+        Ok(Box::new(standard::Time::new(
+            o.format.as_deref(),
+            o.timezone.as_deref(),
+            o.locale.as_deref(),
+        )?))
+    }),
 ];
 
-pub fn mk_widget(
-    name: &str,
-    section: &toml::map::Map<String, toml::Value>,
-) -> Result<Box<dyn Widget>, failure::Error> {
+/// Build the widget called `name` out of its section of the config,
+/// which keeps track of any keys it didn't know what to do with
+pub fn mk_widget(name: &str, section: &mut Section) -> Result<Box<dyn Widget>, failure::Error> {
     for (n, f) in ALL_WIDGETS.iter() {
         if n == &name {
             return f(section);
         }
     }
-    Err(format_err!("No widget type named {}", name))
+    Err(KeyError {
+        key: Some("name".to_string()),
+        message: format!(
+            "no widget type named {:?}: expected one of {}",
+            name,
+            ALL_WIDGETS
+                .iter()
+                .map(|(n, _)| format!("`{}`", n))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+    .into())
 }
@@ -1,12 +1,14 @@
-use crate::widgets::widget::{Drawing, Located, Widget};
+use crate::widgets::widget::{Drawing, Frequency, Located, Widget};
 
 use std::io::{BufRead, BufReader, Write};
 use std::net::TcpStream;
+use std::sync::Mutex;
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct MPD {
     host: String,
     port: usize,
-    last_state: State,
+    last_state: Mutex<State>,
 }
 
 enum State {
@@ -16,7 +18,7 @@ enum State {
 
 impl MPD {
     pub fn new(host: String, port: usize) -> MPD {
-        let last_state = State::Stopped;
+        let last_state = Mutex::new(State::Stopped);
         MPD {
             host,
             port,
@@ -34,7 +36,7 @@ impl MPD {
         }
         buf.clear();
 
-        stream.write(b"currentsong\n")?;
+        stream.write_all(b"currentsong\n")?;
         let mut title = None;
         let mut artist = None;
 
@@ -67,19 +69,23 @@ impl MPD {
 
 impl Widget for MPD {
     fn draw(&self, d: &Drawing, loc: Located) -> i32 {
-        match self.last_state {
-            State::Playing(ref song) => loc.draw_text(d, &format!("[{}]", song)),
-            State::Stopped => loc.draw_text(d, &format!("[N/A]")),
+        match *self.last_state.lock().unwrap() {
+            State::Playing(ref song) => loc.draw_text(d, &format!("[{}]", d.escape(song))),
+            State::Stopped => loc.draw_text(d, "[N/A]"),
         }
     }
 
-    fn update_frequency(&self) -> Option<u64> {
-        Some(5)
+    fn update_frequency(&self) -> Option<Frequency> {
+        Some(Frequency::seconds(5))
     }
 
-    fn update(&mut self) {
+    fn update_blocks(&self) -> bool {
+        true
+    }
+
+    fn update(&self) {
         match self.get_song() {
-            Ok(state) => self.last_state = state,
+            Ok(state) => *self.last_state.lock().unwrap() = state,
             Err(err) => eprintln!("Failed to update MPD status: {}", err),
         }
     }
@@ -0,0 +1,352 @@
+use crate::widgets::options::KeyError;
+use crate::widgets::template::Template;
+use crate::widgets::widget::{Drawing, Frequency, Located, Widget};
+
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_FORMAT: &str = "{iface} [{state}] {ipv4} ↓{rx} ↑{tx}";
+
+const KEYS: &[&str] = &["iface", "state", "ipv4", "rx", "tx"];
+
+pub enum Interface {
+    Named(String),
+    // whatever interface the default route goes through right now
+    Auto,
+}
+
+impl Interface {
+    /// The interface doesn't have to exist yet, since things like USB
+    /// dongles come and go, but it does have to be something that
+    /// could be the name of one
+    pub fn from_str(s: &str) -> Result<Interface, failure::Error> {
+        if s == "auto" {
+            return Ok(Interface::Auto);
+        }
+        // Linux keeps these to 15 bytes, and they end up as file
+        // names in sysfs
+        let valid = !s.is_empty()
+            && s.len() < 16
+            && s != "."
+            && s != ".."
+            && !s.chars().any(|c| c == '/' || c == ':' || c.is_whitespace());
+        if !valid {
+            return Err(KeyError {
+                key: Some("interface".to_string()),
+                message: format!("{:?} isn't a valid interface name", s),
+            }
+            .into());
+        }
+        Ok(Interface::Named(s.to_string()))
+    }
+}
+
+/// The raw counters from sysfs, and when we read them
+struct Counters {
+    iface: String,
+    when: Instant,
+    rx: u64,
+    tx: u64,
+}
+
+#[derive(Default)]
+struct Status {
+    iface: Option<String>,
+    state: String,
+    ipv4: Option<Ipv4Addr>,
+    // both of these are in bytes per second
+    rx_rate: f64,
+    tx_rate: f64,
+}
+
+impl Status {
+    fn down(interface: &Interface) -> Status {
+        Status {
+            iface: match interface {
+                Interface::Named(name) => Some(name.clone()),
+                Interface::Auto => None,
+            },
+            state: "down".to_string(),
+            ..Status::default()
+        }
+    }
+
+    fn lookup(&self, key: &str) -> String {
+        match key {
+            "iface" => self.iface.clone().unwrap_or_else(|| "-".to_string()),
+            "state" => self.state.clone(),
+            "ipv4" => self
+                .ipv4
+                .map(|a| a.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            "rx" => human_rate(self.rx_rate),
+            "tx" => human_rate(self.tx_rate),
+            _ => String::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Readings {
+    last_counters: Option<Counters>,
+    status: Status,
+}
+
+pub struct Network {
+    interface: Interface,
+    sys_root: PathBuf,
+    proc_root: PathBuf,
+    template: Template,
+    interval: Duration,
+    readings: Mutex<Readings>,
+}
+
+impl Network {
+    pub fn new(
+        interface: Interface,
+        sys_root: &Path,
+        proc_root: &Path,
+        format: &str,
+        interval: Duration,
+    ) -> Result<Network, failure::Error> {
+        let template = Template::parse("format", format, KEYS)?;
+        let readings = Readings {
+            last_counters: None,
+            status: Status::down(&interface),
+        };
+        Ok(Network {
+            interface,
+            sys_root: sys_root.to_path_buf(),
+            proc_root: proc_root.to_path_buf(),
+            template,
+            interval,
+            readings: Mutex::new(readings),
+        })
+    }
+
+    fn current_interface(&self) -> Result<String, failure::Error> {
+        match self.interface {
+            Interface::Named(ref name) => Ok(name.clone()),
+            Interface::Auto => default_route_interface(&self.proc_root.join("net/route")),
+        }
+    }
+
+    fn read_counters(&self, iface: &str) -> Result<Counters, failure::Error> {
+        let stats = self
+            .sys_root
+            .join("class/net")
+            .join(iface)
+            .join("statistics");
+        let read = |name| -> Result<u64, failure::Error> {
+            Ok(std::fs::read_to_string(stats.join(name))?.trim().parse()?)
+        };
+        Ok(Counters {
+            iface: iface.to_string(),
+            when: Instant::now(),
+            rx: read("rx_bytes")?,
+            tx: read("tx_bytes")?,
+        })
+    }
+
+    fn read_status(&self) -> Result<(), failure::Error> {
+        let iface = self.current_interface()?;
+        // an interface that's not there (yet) is just down, rather
+        // than anything worth complaining about
+        if !self.sys_root.join("class/net").join(&iface).exists() {
+            if let Interface::Named(_) = self.interface {
+                *self.readings.lock().unwrap() = Readings {
+                    last_counters: None,
+                    status: Status::down(&self.interface),
+                };
+                return Ok(());
+            }
+        }
+        let state_path = self
+            .sys_root
+            .join("class/net")
+            .join(&iface)
+            .join("operstate");
+        let state = std::fs::read_to_string(state_path)?.trim().to_string();
+        let counters = self.read_counters(&iface)?;
+        let ipv4 = ipv4_address(&iface);
+
+        // rates only make sense if we're comparing against the same
+        // interface: if the default route moved, start over
+        let mut readings = self.readings.lock().unwrap();
+        let (rx_rate, tx_rate) = match readings.last_counters {
+            Some(ref last) if last.iface == iface => {
+                let secs = counters.when.duration_since(last.when).as_secs_f64();
+                if secs > 0.0 {
+                    (
+                        counters.rx.saturating_sub(last.rx) as f64 / secs,
+                        counters.tx.saturating_sub(last.tx) as f64 / secs,
+                    )
+                } else {
+                    (readings.status.rx_rate, readings.status.tx_rate)
+                }
+            }
+            _ => (0.0, 0.0),
+        };
+
+        readings.status = Status {
+            ipv4,
+            iface: Some(iface),
+            state,
+            rx_rate,
+            tx_rate,
+        };
+        readings.last_counters = Some(counters);
+        Ok(())
+    }
+}
+
+/// Find the interface used by the default route, preferring the one
+/// with the lowest metric if there's more than one
+fn default_route_interface(path: &Path) -> Result<String, failure::Error> {
+    const RTF_UP: u32 = 0x1;
+    let body = std::fs::read_to_string(path)?;
+    let mut best: Option<(u32, String)> = None;
+    for line in body.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 8 {
+            continue;
+        }
+        let flags = u32::from_str_radix(fields[3], 16).unwrap_or(0);
+        if fields[1] != "00000000" || fields[7] != "00000000" || flags & RTF_UP == 0 {
+            continue;
+        }
+        let metric = fields[6].parse().unwrap_or(u32::MAX);
+        if best.as_ref().is_none_or(|(m, _)| metric < *m) {
+            best = Some((metric, fields[0].to_string()));
+        }
+    }
+    best.map(|(_, iface)| iface)
+        .ok_or_else(|| format_err!("No default route in {}", path.display()))
+}
+
+fn ipv4_address(iface: &str) -> Option<Ipv4Addr> {
+    let mut result = None;
+    unsafe {
+        let mut addrs = std::ptr::null_mut();
+        if libc::getifaddrs(&mut addrs) != 0 {
+            return None;
+        }
+        let mut cur = addrs;
+        while let Some(addr) = cur.as_ref() {
+            if !addr.ifa_addr.is_null()
+                && i32::from((*addr.ifa_addr).sa_family) == libc::AF_INET
+                && std::ffi::CStr::from_ptr(addr.ifa_name).to_bytes() == iface.as_bytes()
+            {
+                let sin = &*(addr.ifa_addr as *const libc::sockaddr_in);
+                result = Some(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)));
+                break;
+            }
+            cur = addr.ifa_next;
+        }
+        libc::freeifaddrs(addrs);
+    }
+    result
+}
+
+fn human_rate(bytes_per_sec: f64) -> String {
+    const UNITS: &[&str] = &["B", "K", "M", "G"];
+    let mut amt = bytes_per_sec;
+    let mut unit = 0;
+    while amt >= 1024.0 && unit < UNITS.len() - 1 {
+        amt /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0}{}", amt, UNITS[unit])
+    } else {
+        format!("{:.1}{}", amt, UNITS[unit])
+    }
+}
+
+impl Widget for Network {
+    fn draw(&self, d: &Drawing, loc: Located) -> i32 {
+        let readings = self.readings.lock().unwrap();
+        let text = self
+            .template
+            .render(|k| d.escape(&readings.status.lookup(k)).into_owned());
+        loc.draw_text(d, &text)
+    }
+
+    fn update_frequency(&self) -> Option<Frequency> {
+        Some(Frequency::Every(self.interval))
+    }
+
+    fn update_blocks(&self) -> bool {
+        true
+    }
+
+    fn update(&self) {
+        if let Err(err) = self.read_status() {
+            *self.readings.lock().unwrap() = Readings {
+                last_counters: None,
+                status: Status::down(&self.interface),
+            };
+            eprintln!("Failed to update network status: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn interface_names() {
+        assert!(matches!(Interface::from_str("auto"), Ok(Interface::Auto)));
+        assert!(matches!(
+            Interface::from_str("wlan0"),
+            Ok(Interface::Named(_))
+        ));
+        for bad in &["", "..", "eth/0", "eth 0", "averyveryverylongname"] {
+            let err = Interface::from_str(bad).err().unwrap();
+            let err = err.downcast_ref::<KeyError>().unwrap();
+            assert_eq!(err.key.as_deref(), Some("interface"));
+        }
+    }
+
+    #[test]
+    fn missing_interface_is_down() {
+        let root = TempDir::new("net");
+        std::fs::create_dir_all(root.path().join("class/net")).unwrap();
+        let net = Network::new(
+            Interface::from_str("usb0").unwrap(),
+            root.path(),
+            root.path(),
+            "{iface} {state}",
+            Duration::from_secs(1),
+        )
+        .unwrap();
+        net.update();
+        let readings = net.readings.lock().unwrap();
+        assert_eq!(readings.status.lookup("iface"), "usb0");
+        assert_eq!(readings.status.lookup("state"), "down");
+    }
+
+    #[test]
+    fn default_route() {
+        let dir = TempDir::new("route");
+        let path = dir.write(
+            "route",
+            "Iface\tDestination\tGateway\tFlags\tRefCnt\tUse\tMetric\tMask\n\
+             wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\n\
+             eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\n\
+             eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\n",
+        );
+        assert_eq!(default_route_interface(&path).unwrap(), "eth0");
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(human_rate(512.0), "512B");
+        assert_eq!(human_rate(1536.0), "1.5K");
+        assert_eq!(human_rate(3.0 * 1024.0 * 1024.0), "3.0M");
+    }
+}
@@ -0,0 +1,302 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::widgets::WidgetSection;
+use crate::widgets::{command, cpu, standard};
+
+/// A problem with a particular key in a widget's section, which we
+/// keep separate so that whoever's reporting it can point at the
+/// right line of the config
+#[derive(Debug)]
+pub struct KeyError {
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for KeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.key {
+            Some(ref key) => write!(f, "`{}`: {}", key, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// A widget's section of the config, which keeps track of any keys
+/// that didn't get used so we can warn about them
+pub struct Section<'a> {
+    table: &'a WidgetSection,
+    pub unused: Vec<String>,
+}
+
+impl<'a> Section<'a> {
+    pub fn new(table: &'a WidgetSection) -> Section<'a> {
+        Section {
+            table,
+            unused: Vec::new(),
+        }
+    }
+
+    /// Deserialize the section into `T`, recording which keys `T`
+    /// didn't care about
+    pub fn parse<T: DeserializeOwned>(&mut self) -> Result<T, failure::Error> {
+        let mut unused: Vec<String> = Vec::new();
+        let value = toml::Value::Table(self.table.clone());
+        let mut record = |path: serde_ignored::Path| unused.push(path.to_string());
+        let de = serde_ignored::Deserializer::new(value, &mut record);
+        let result = serde_path_to_error::deserialize(de);
+        self.unused = unused;
+        result.map_err(|err| {
+            let key = err.path().to_string();
+            // the TOML library tacks the key onto the end of its
+            // messages, but we'd rather put it first
+            let message = err.into_inner().to_string();
+            let message = message
+                .strip_suffix(&format!(" for key `{}`", key))
+                .unwrap_or(&message)
+                .to_string();
+            KeyError {
+                key: if key == "." { None } else { Some(key) },
+                message,
+            }
+            .into()
+        })
+    }
+}
+
+/// A length of time, written in the config as a (possibly fractional)
+/// number of seconds
+#[derive(Debug, Clone, Copy)]
+pub struct Seconds(pub Duration);
+
+/// The longest a `Seconds` is allowed to be. Nothing on a status bar
+/// needs to wait longer than this, and anything much bigger stops
+/// fitting in an `Instant` once it's added on to one.
+const MAX_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+impl<'de> Deserialize<'de> for Seconds {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Seconds, D::Error> {
+        struct Visitor;
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Seconds;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                // zero would have us updating in a busy loop
+                write!(f, "a number of seconds above 0 and at most {}", MAX_SECONDS)
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, n: u64) -> Result<Seconds, E> {
+                if n == 0 || n > MAX_SECONDS {
+                    return Err(E::invalid_value(serde::de::Unexpected::Unsigned(n), &self));
+                }
+                Ok(Seconds(Duration::from_secs(n)))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, n: i64) -> Result<Seconds, E> {
+                if n <= 0 || n as u64 > MAX_SECONDS {
+                    return Err(E::invalid_value(serde::de::Unexpected::Signed(n), &self));
+                }
+                Ok(Seconds(Duration::from_secs(n as u64)))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, n: f64) -> Result<Seconds, E> {
+                // this is written so that NaN fails it too
+                if !(n > 0.0 && n <= MAX_SECONDS as f64) {
+                    return Err(E::invalid_value(serde::de::Unexpected::Float(n), &self));
+                }
+                Ok(Seconds(Duration::from_secs_f64(n)))
+            }
+        }
+        d.deserialize_any(Visitor)
+    }
+}
+
+/// The most room a widget is allowed to take up, which is written in
+/// the config as either a number of pixels (e.g. `300` or `"300px"`)
+/// or a number of characters (e.g. `"40ch"`)
+#[derive(Debug, Clone, Copy)]
+pub enum MaxWidth {
+    Pixels(i32),
+    Chars(i32),
+}
+
+impl MaxWidth {
+    /// How many pixels this is, using the layout's font to figure out
+    /// how wide a character is
+    pub fn pixels(self, lyt: &pango::Layout) -> i32 {
+        use pango::{ContextExt, LayoutExt};
+        match self {
+            MaxWidth::Pixels(n) => n,
+            MaxWidth::Chars(n) => {
+                let char_width = lyt
+                    .get_context()
+                    .and_then(|ctx| ctx.get_metrics(lyt.get_font_description().as_ref(), None))
+                    .map_or(0, |m| m.get_approximate_char_width() / pango::SCALE);
+                n * char_width
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MaxWidth {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<MaxWidth, D::Error> {
+        struct Visitor;
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = MaxWidth;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(
+                    f,
+                    "a number of pixels, or a string like \"300px\" or \"40ch\""
+                )
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, n: i64) -> Result<MaxWidth, E> {
+                match i32::try_from(n) {
+                    Ok(n) if n >= 0 => Ok(MaxWidth::Pixels(n)),
+                    _ => Err(E::invalid_value(serde::de::Unexpected::Signed(n), &self)),
+                }
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<MaxWidth, E> {
+                let s = s.trim();
+                let (num, unit): (&str, fn(i32) -> MaxWidth) = if let Some(n) = s.strip_suffix("px")
+                {
+                    (n, MaxWidth::Pixels)
+                } else if let Some(n) = s.strip_suffix("ch") {
+                    (n, MaxWidth::Chars)
+                } else {
+                    return Err(E::invalid_value(serde::de::Unexpected::Str(s), &self));
+                };
+                match num.trim().parse::<i32>() {
+                    Ok(n) if n >= 0 => Ok(unit(n)),
+                    _ => Err(E::invalid_value(serde::de::Unexpected::Str(s), &self)),
+                }
+            }
+        }
+        d.deserialize_any(Visitor)
+    }
+}
+
+// What follows are the options for each kind of widget. Anything
+// optional gets its default where the widget gets built, in
+// `widgets/mod.rs`.
+
+/// For widgets that don't take any options at all
+#[derive(Deserialize)]
+pub struct NoOptions {}
+
+#[derive(Deserialize)]
+pub struct CommandOptions {
+    pub command: String,
+    pub interval: Option<Seconds>,
+    pub timeout: Option<Seconds>,
+    pub line: Option<command::Line>,
+    pub error_text: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CpuOptions {
+    pub mode: Option<cpu::Mode>,
+    pub proc_root: Option<PathBuf>,
+    pub interval: Option<Seconds>,
+}
+
+#[derive(Deserialize)]
+pub struct ExecOptions {
+    pub command: String,
+}
+
+#[derive(Deserialize)]
+pub struct I3barOptions {
+    pub command: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryStyle {
+    Text,
+    Gauge,
+}
+
+#[derive(Deserialize)]
+pub struct MemoryOptions {
+    pub style: Option<MemoryStyle>,
+    pub format: Option<String>,
+    pub threshold: Option<f64>,
+    pub proc_root: Option<PathBuf>,
+    pub interval: Option<Seconds>,
+}
+
+#[derive(Deserialize)]
+pub struct MpdOptions {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Deserialize)]
+pub struct NetworkOptions {
+    pub interface: Option<String>,
+    pub sys_root: Option<PathBuf>,
+    pub proc_root: Option<PathBuf>,
+    pub format: Option<String>,
+    pub interval: Option<Seconds>,
+}
+
+#[derive(Deserialize)]
+pub struct StdinOptions {
+    pub format: Option<standard::StdinFormat>,
+    pub icon_root: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+pub struct TextOptions {
+    pub text: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TimeOptions {
+    pub format: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Interval {
+        interval: Seconds,
+    }
+
+    fn parse(value: &str) -> Result<Duration, KeyError> {
+        let table: WidgetSection = toml::from_str(&format!("interval = {}", value)).unwrap();
+        let mut section = Section::new(&table);
+        section
+            .parse::<Interval>()
+            .map(|i| i.interval.0)
+            .map_err(|err| err.downcast::<KeyError>().unwrap())
+    }
+
+    #[test]
+    fn seconds() {
+        assert_eq!(parse("5").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse("0.25").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse("604800").unwrap(), Duration::from_secs(MAX_SECONDS));
+    }
+
+    #[test]
+    fn bad_seconds() {
+        for value in &["0", "0.0", "-1", "-0.5", "nan", "inf", "604801", "1e300"] {
+            let err = parse(value).unwrap_err();
+            assert_eq!(err.key.as_deref(), Some("interval"), "{}", value);
+        }
+    }
+}
@@ -1,40 +1,277 @@
-pub use crate::widgets::widget::{Drawing, Located, Size, Widget};
+pub use crate::widgets::widget::{Drawing, Frequency, Located, Widget};
+
+use crate::config::Section;
+use crate::widgets::options::KeyError;
+use crate::widgets::styled::Styled;
+use crate::widgets::widget::Button;
+use crate::widgets::{command, lemonbar, xmobar};
+
+use chrono::format::{Fixed, Item, Numeric, StrftimeItems};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug)]
 pub struct Time {
     fmt: String,
+    tz: Option<Zone>,
+    locale: Option<chrono::Locale>,
+    frequency: Frequency,
+}
+
+/// Where the rules for a `timezone` came from
+enum Zone {
+    // the system's own tzdata, so we agree with everything else on
+    // the machine even if it's newer than what we were built with
+    System(tz::TimeZone),
+    // the copy of the database that's built into chrono-tz, for
+    // systems without tzdata installed
+    Bundled(chrono_tz::Tz),
+}
+
+/// Where the system keeps its tzdata, which `TZDIR` can override
+fn tzdir() -> PathBuf {
+    std::env::var_os("TZDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/zoneinfo"))
+}
+
+/// Find the time zone called `name` (an IANA name, like
+/// `Asia/Tokyo`) in `tzdir`, or failing that in chrono-tz's bundled
+/// copy of the database
+fn load_zone(name: &str, tzdir: &Path) -> Result<Zone, KeyError> {
+    let unknown = || KeyError {
+        key: Some("timezone".to_string()),
+        message: format!("unknown time zone {:?}", name),
+    };
+    // only take plain names, so that nobody can point us at some
+    // other file with `..`, and so that POSIX rules like
+    // `EST5EDT,M3.2.0,M11.1.0` don't look like names
+    let plain = name.split('/').all(|part| {
+        !part.is_empty()
+            && !part.starts_with('.')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-+.".contains(c))
+    });
+    if !plain {
+        return Err(unknown());
+    }
+
+    let system = std::fs::read(tzdir.join(name))
+        .ok()
+        .and_then(|data| tz::TimeZone::from_tz_data(&data).ok());
+    match system {
+        Some(zone) => Ok(Zone::System(zone)),
+        None => name.parse().map(Zone::Bundled).map_err(|_| unknown()),
+    }
 }
 
 impl Time {
-    pub fn new() -> Time {
-        Time {
-            fmt: "%a %b %d %H:%M".to_string(),
+    pub const DEFAULT_FORMAT: &'static str = "%a %b %d %H:%M";
+
+    pub fn new(
+        fmt: Option<&str>,
+        tz: Option<&str>,
+        locale: Option<&str>,
+    ) -> Result<Time, failure::Error> {
+        let fmt = fmt.unwrap_or(Time::DEFAULT_FORMAT);
+        // chrono will happily accept a bad format string and then
+        // panic when we try to print it, so check it up-front
+        if StrftimeItems::new(fmt).any(|i| i == Item::Error) {
+            return Err(KeyError {
+                key: Some("format".to_string()),
+                message: format!("invalid time format string {:?}", fmt),
+            }
+            .into());
+        }
+
+        // these are the IANA names, like `Asia/Tokyo`, which means
+        // we know their abbreviations for `%Z` too
+        let tz = match tz {
+            Some(name) => Some(load_zone(name, &tzdir())?),
+            None => None,
+        };
+
+        let locale = match locale {
+            // people tend to write the same thing they'd put in
+            // `LANG`, so drop any encoding suffix
+            Some(name) => {
+                let name = name.split('.').next().unwrap_or(name);
+                Some(chrono::Locale::try_from(name).map_err(|_| KeyError {
+                    key: Some("locale".to_string()),
+                    message: format!("unknown locale {:?}", name),
+                })?)
+            }
+            None => None,
+        };
+
+        // if we're showing seconds then we need to redraw every
+        // second, but otherwise once a minute will do
+        let shows_seconds = StrftimeItems::new(fmt).any(|i| {
+            matches!(
+                i,
+                Item::Numeric(Numeric::Second, _)
+                    | Item::Numeric(Numeric::Timestamp, _)
+                    | Item::Numeric(Numeric::Nanosecond, _)
+                    | Item::Fixed(Fixed::RFC2822)
+                    | Item::Fixed(Fixed::RFC3339)
+            )
+        });
+        let frequency = if shows_seconds {
+            Frequency::Aligned(std::time::Duration::from_secs(1))
+        } else {
+            Frequency::Aligned(std::time::Duration::from_secs(60))
+        };
+
+        Ok(Time {
+            fmt: fmt.to_string(),
+            frequency,
+            tz,
+            locale,
+        })
+    }
+
+    /// What the clock should say at `now`
+    fn format(&self, now: chrono::DateTime<chrono::Utc>) -> String {
+        let items = StrftimeItems::new(&self.fmt);
+        match self.tz {
+            Some(Zone::System(ref tz)) => match tz.find_local_time_type(now.timestamp()) {
+                Ok(ty) => {
+                    let offset = chrono::FixedOffset::east_opt(ty.ut_offset())
+                        .unwrap_or_else(|| chrono::FixedOffset::east_opt(0).unwrap());
+                    // a `FixedOffset` doesn't know what it's called,
+                    // so fill in `%Z` with the zone's abbreviation
+                    let items = items.map(|i| match i {
+                        Item::Fixed(Fixed::TimezoneName) => {
+                            Item::Literal(ty.time_zone_designation())
+                        }
+                        i => i,
+                    });
+                    self.format_items(now.with_timezone(&offset), items)
+                }
+                Err(_) => self.format_items(now, items),
+            },
+            Some(Zone::Bundled(tz)) => self.format_items(now.with_timezone(&tz), items),
+            None => self.format_items(now.with_timezone(&chrono::Local), items),
+        }
+    }
+
+    fn format_items<'a, Z, I>(&self, time: chrono::DateTime<Z>, items: I) -> String
+    where
+        Z: chrono::TimeZone,
+        Z::Offset: std::fmt::Display,
+        I: Iterator<Item = Item<'a>> + Clone,
+    {
+        match self.locale {
+            Some(locale) => time.format_localized_with_items(items, locale).to_string(),
+            None => time.format_with_items(items).to_string(),
         }
     }
 }
 
 impl Widget for Time {
     fn draw(&self, d: &Drawing, loc: Located) -> i32 {
-        let now = chrono::Local::now();
-        loc.draw_text(d, &format!("{}", &now.format(&self.fmt)))
+        loc.draw_text(d, &self.format(chrono::Utc::now()))
     }
+
+    // there's nothing to actually do on update, but this makes sure
+    // the main loop wakes up to redraw us when the time changes
+    fn update_frequency(&self) -> Option<Frequency> {
+        Some(self.frequency)
+    }
+}
+
+/// How to read what comes in on stdin
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StdinFormat {
+    Plain,
+    // the markup xmobar understands, e.g. from XMonad's `xmobarPP`
+    Xmobar,
+    // lemonbar's `%{...}` blocks
+    Lemonbar,
 }
 
-#[derive(Debug)]
-pub struct Stdin;
+pub struct Stdin {
+    format: StdinFormat,
+    styled: Styled,
+    // which of lemonbar's alignments this one draws
+    align: Section,
+}
 
 impl Stdin {
-    pub fn new() -> Stdin {
-        Stdin
+    pub fn new(format: StdinFormat, icon_root: Option<PathBuf>) -> Stdin {
+        Stdin {
+            format,
+            styled: Styled::new(icon_root),
+            align: Section::Left,
+        }
     }
 }
 
 impl Widget for Stdin {
     fn draw(&self, d: &Drawing, loc: Located) -> i32 {
-        loc.draw_text(d, &d.stdin)
+        match self.format {
+            StdinFormat::Plain => loc.draw_text(d, d.stdin),
+            StdinFormat::Xmobar => self.styled.draw(d, loc, &xmobar::parse(d.stdin)),
+            StdinFormat::Lemonbar => {
+                self.styled
+                    .draw(d, loc, &lemonbar::parse(d.stdin, self.align))
+            }
+        }
+    }
+
+    // lemonbar input says for itself which part of the bar things go
+    // in, so this draws the left-hand part and these draw the rest
+    fn companions(&self) -> Vec<(Section, Box<dyn Widget>)> {
+        match self.format {
+            StdinFormat::Lemonbar => [Section::Center, Section::Right]
+                .iter()
+                .map(|&align| {
+                    let part: Box<dyn Widget> = Box::new(Stdin {
+                        format: self.format,
+                        styled: Styled::new(None),
+                        align,
+                    });
+                    (align, part)
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn on_click(&self, button: Button, x: f64, _y: f64) {
+        for command in self.styled.actions_at(button, x) {
+            command::spawn(&command);
+        }
     }
 }
 
+/// Some fixed text, which can be changed from outside using
+/// `knurling msg set`
+pub struct Text {
+    text: std::sync::Mutex<String>,
+}
+
+impl Text {
+    pub fn new(text: &str) -> Text {
+        Text {
+            text: std::sync::Mutex::new(text.to_string()),
+        }
+    }
+}
+
+impl Widget for Text {
+    fn draw(&self, d: &Drawing, loc: Located) -> i32 {
+        loc.draw_text(d, &self.text.lock().unwrap())
+    }
+
+    fn set_text(&self, text: &str) -> Result<(), failure::Error> {
+        *self.text.lock().unwrap() = text.to_string();
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
 pub struct SmallBox;
 
 impl Widget for SmallBox {
@@ -58,3 +295,93 @@ impl Widget for Caesura {
         2
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use chrono::TimeZone;
+
+    #[test]
+    fn unknown_time_zone_is_a_key_error() {
+        let err = Time::new(None, Some("Not/AZone"), None).err().unwrap();
+        let err = err.downcast_ref::<KeyError>().unwrap();
+        assert_eq!(err.key.as_deref(), Some("timezone"));
+    }
+
+    #[test]
+    fn posix_tz_strings_are_rejected() {
+        assert!(Time::new(None, Some("EST5EDT,M3.2.0,M11.1.0"), None).is_err());
+    }
+
+    #[test]
+    fn zone_abbreviation() {
+        let time = Time::new(Some("%H:%M %Z"), Some("Asia/Tokyo"), None).unwrap();
+        let then = chrono::Utc.timestamp_opt(0, 0).unwrap();
+        assert_eq!(time.format(then), "09:00 JST");
+    }
+
+    /// A TZif file for a zone that's always 5½ hours ahead of UTC and
+    /// calls itself `XST`
+    fn tzif() -> Vec<u8> {
+        let mut data = b"TZif".to_vec();
+        data.extend(&[0; 16]);
+        // the counts of UT/local indicators, standard/wall indicators,
+        // leap seconds, transitions, time types and abbreviation bytes
+        for count in &[0u32, 0, 0, 0, 1, 4] {
+            data.extend(&count.to_be_bytes());
+        }
+        data.extend(&(5 * 3600 + 1800i32).to_be_bytes());
+        data.extend(&[0, 0]);
+        data.extend(b"XST\0");
+        data
+    }
+
+    #[test]
+    fn system_tzdata_comes_first() {
+        let dir = TempDir::new("tzdata");
+        dir.write("Test/Nowhere", tzif());
+        let zone = load_zone("Test/Nowhere", dir.path()).unwrap();
+        assert!(matches!(zone, Zone::System(_)));
+        let time = Time {
+            tz: Some(zone),
+            ..Time::new(Some("%H:%M %Z"), None, None).unwrap()
+        };
+        let then = chrono::Utc.timestamp_opt(0, 0).unwrap();
+        assert_eq!(time.format(then), "05:30 XST");
+
+        // anything the system doesn't have comes out of the bundled
+        // database instead
+        let zone = load_zone("Asia/Tokyo", dir.path()).unwrap();
+        assert!(matches!(zone, Zone::Bundled(chrono_tz::Asia::Tokyo)));
+    }
+
+    #[test]
+    fn only_plain_zone_names() {
+        let dir = TempDir::new("tzdata-names");
+        let path = dir.write("Test/Nowhere", tzif());
+        let outside = path.to_str().unwrap();
+        for name in &[outside, "../Test/Nowhere", "Test/../Test/Nowhere", ""] {
+            let err = load_zone(name, &dir.path().join("Test")).err().unwrap();
+            assert_eq!(err.key.as_deref(), Some("timezone"));
+        }
+    }
+
+    #[test]
+    fn bad_format_string() {
+        let err = Time::new(Some("%Q"), None, None).err().unwrap();
+        let err = err.downcast_ref::<KeyError>().unwrap();
+        assert_eq!(err.key.as_deref(), Some("format"));
+    }
+
+    #[test]
+    fn locales() {
+        let time = Time::new(Some("%A"), Some("UTC"), Some("de_DE.UTF-8")).unwrap();
+        let then = chrono::Utc.timestamp_opt(0, 0).unwrap();
+        assert_eq!(time.format(then), "Donnerstag");
+
+        let err = Time::new(None, None, Some("xx_YY")).err().unwrap();
+        let err = err.downcast_ref::<KeyError>().unwrap();
+        assert_eq!(err.key.as_deref(), Some("locale"));
+    }
+}
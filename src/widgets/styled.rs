@@ -0,0 +1,380 @@
+use crate::widgets::widget::{escape_markup, Button, Drawing, Located};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+thread_local! {
+    // the surfaces that icons get drawn from, so they only get made
+    // once per icon rather than on every redraw. Cairo surfaces can't
+    // be shared between threads, so these belong to whichever thread
+    // is doing the drawing rather than to the widget.
+    static SURFACES: RefCell<HashMap<PathBuf, cairo::ImageSurface>> =
+        RefCell::new(HashMap::new());
+}
+
+/// A command to run when part of the text gets clicked with one of
+/// `buttons`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    pub command: String,
+    pub buttons: Vec<Button>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Text(String),
+    Icon(PathBuf),
+    // an empty gap this many pixels wide
+    Space(i32),
+}
+
+/// A piece of the bar's input with the same styling all the way
+/// through, which is what the various status-line formats get parsed
+/// into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub content: Content,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    // underlining uses the text's own color unless it's been given
+    // one of its own
+    pub underline: bool,
+    pub underline_color: Option<String>,
+    pub actions: Vec<Action>,
+}
+
+impl Run {
+    /// Turn this run into Pango markup, if it's text
+    fn markup(&self) -> Option<String> {
+        let text = match self.content {
+            Content::Text(ref text) => escape_markup(text),
+            _ => return None,
+        };
+        let mut attrs = String::new();
+        if let Some(ref fg) = self.fg {
+            attrs.push_str(&format!(" foreground=\"{}\"", escape_markup(fg)));
+        }
+        if let Some(ref bg) = self.bg {
+            attrs.push_str(&format!(" background=\"{}\"", escape_markup(bg)));
+        }
+        if self.underline {
+            attrs.push_str(" underline=\"single\"");
+            if let Some(ref color) = self.underline_color {
+                attrs.push_str(&format!(" underline_color=\"{}\"", escape_markup(color)));
+            }
+        }
+        if attrs.is_empty() {
+            Some(text)
+        } else {
+            Some(format!("<span{}>{}</span>", attrs, text))
+        }
+    }
+}
+
+/// Add `run` to the end of `runs`, tacking it onto the last one if
+/// they're both text and styled the same
+pub fn push_run(runs: &mut Vec<Run>, run: Run) {
+    if let (Content::Text(ref new), Some(last)) = (&run.content, runs.last_mut()) {
+        let same_style = last.fg == run.fg
+            && last.bg == run.bg
+            && last.underline == run.underline
+            && last.underline_color == run.underline_color
+            && last.actions == run.actions;
+        if let (Content::Text(ref mut text), true) = (&mut last.content, same_style) {
+            text.push_str(new);
+            return;
+        }
+    }
+    runs.push(run);
+}
+
+/// Whether Pango knows what color `color` is: this covers hex
+/// colors as well as names like `red`
+pub fn valid_color(color: &str) -> bool {
+    let markup = format!("<span foreground=\"{}\">x</span>", escape_markup(color));
+    pango::parse_markup(&markup, '\0').is_ok()
+}
+
+/// A monochrome XBM image, stored as a Cairo A8 mask so it can get
+/// drawn in whatever the current color is
+struct Icon {
+    width: i32,
+    height: i32,
+    stride: i32,
+    data: Vec<u8>,
+}
+
+impl Icon {
+    fn load(path: &Path) -> Result<Icon, failure::Error> {
+        let body = std::fs::read_to_string(path)?;
+        let dimension = |suffix: &str| -> Result<i32, failure::Error> {
+            body.lines()
+                .filter_map(|l| l.trim().strip_prefix("#define "))
+                .filter_map(|l| {
+                    let mut parts = l.split_whitespace();
+                    let name = parts.next()?;
+                    let value = parts.next()?;
+                    if name.ends_with(suffix) {
+                        value.parse().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .ok_or_else(|| format_err!("{} isn't an XBM file", path.display()))
+        };
+        let width = dimension("_width")?;
+        let height = dimension("_height")?;
+
+        let bits = body
+            .split_once('{')
+            .map(|(_, b)| b)
+            .ok_or_else(|| format_err!("{} isn't an XBM file", path.display()))?;
+        let bytes = bits
+            .split(|c: char| c == ',' || c == '}' || c.is_whitespace())
+            .filter_map(|b| b.strip_prefix("0x").or_else(|| b.strip_prefix("0X")))
+            .map(|b| u8::from_str_radix(b, 16))
+            .collect::<Result<Vec<u8>, _>>()?;
+
+        // XBM rows are padded out to a whole byte, with the leftmost
+        // pixel in the lowest bit
+        let row_bytes = (width as usize).div_ceil(8);
+        if bytes.len() < row_bytes * height as usize {
+            bail!("{} is missing some of its pixels", path.display());
+        }
+        let stride = cairo::Format::A8
+            .stride_for_width(width as u32)
+            .map_err(|_| format_err!("{} is too wide", path.display()))?;
+        let mut data = vec![0; (stride * height) as usize];
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                if bytes[y * row_bytes + x / 8] & (1 << (x % 8)) != 0 {
+                    data[y * stride as usize + x] = 0xff;
+                }
+            }
+        }
+        Ok(Icon {
+            width,
+            height,
+            stride,
+            data,
+        })
+    }
+
+    /// Draw the icon, which was loaded from `path`
+    fn draw(&self, d: &Drawing, path: &Path, x: f64) {
+        SURFACES.with(|surfaces| {
+            let mut surfaces = surfaces.borrow_mut();
+            if !surfaces.contains_key(path) {
+                match cairo::ImageSurface::create_for_data(
+                    self.data.clone(),
+                    cairo::Format::A8,
+                    self.width,
+                    self.height,
+                    self.stride,
+                ) {
+                    Ok(surf) => surfaces.insert(path.to_path_buf(), surf),
+                    Err(_) => return,
+                };
+            }
+            let y = ((d.size.ht - self.height) / 2) as f64;
+            d.ctx.mask_surface(&surfaces[path], x, y);
+        });
+    }
+}
+
+/// Where a clickable bit of text ended up, relative to the start of
+/// the widget
+struct Region {
+    x: f64,
+    wd: f64,
+    actions: Vec<Action>,
+}
+
+/// Draws runs of styled text, keeping track of where anything
+/// clickable got drawn so that clicks can be matched up to actions
+pub struct Styled {
+    icon_root: Option<PathBuf>,
+    regions: Mutex<Vec<Region>>,
+    // icons get loaded the first time we see them, and if one
+    // doesn't load then we won't keep trying
+    icons: Mutex<HashMap<PathBuf, Option<Icon>>>,
+}
+
+impl Styled {
+    pub fn new(icon_root: Option<PathBuf>) -> Styled {
+        Styled {
+            icon_root,
+            regions: Mutex::new(Vec::new()),
+            icons: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn draw_run(&self, d: &Drawing, x: i32, run: &Run) -> i32 {
+        match run.content {
+            Content::Text(_) => {
+                let markup = run.markup().unwrap_or_default();
+                Located::FromLeft(x).draw_text(&Drawing { markup: true, ..*d }, &markup)
+            }
+            Content::Space(wd) => wd,
+            Content::Icon(ref path) => {
+                let path = match self.icon_root {
+                    Some(ref root) => root.join(path),
+                    None => path.clone(),
+                };
+                let mut icons = self.icons.lock().unwrap();
+                let icon = icons.entry(path.clone()).or_insert_with_key(|path| {
+                    // the file might have changed since some other
+                    // widget (or an older config) drew it
+                    SURFACES.with(|surfaces| surfaces.borrow_mut().remove(path));
+                    Icon::load(path)
+                        .map_err(|err| eprintln!("Unable to load icon: {}", err))
+                        .ok()
+                });
+                match icon {
+                    Some(icon) => {
+                        icon.draw(d, &path, x as f64);
+                        icon.width
+                    }
+                    None => 0,
+                }
+            }
+        }
+    }
+
+    pub fn draw(&self, d: &Drawing, loc: Located, runs: &[Run]) -> i32 {
+        // we need to know how wide it all is before we can tell where
+        // it starts, if it's being drawn from the right
+        let widths: Vec<i32> = runs
+            .iter()
+            .map(|run| {
+                d.ctx.push_group();
+                let wd = self.draw_run(d, 0, run);
+                d.ctx.pop_group();
+                wd
+            })
+            .collect();
+        let natural: i32 = widths.iter().sum();
+        let total = d.limit.map_or(natural, |limit| natural.min(limit));
+        let start = loc.target_x(d, total) as i32;
+
+        let mut regions = Vec::new();
+        let mut x = start;
+        for (run, wd) in runs.iter().zip(widths) {
+            // if we've been squeezed, then the run that doesn't fit
+            // gets cut short, and the rest don't get drawn at all
+            let remaining = start + total - x;
+            if remaining <= 0 {
+                break;
+            }
+            let d = &Drawing {
+                limit: Some(remaining),
+                ..*d
+            };
+            let wd = if wd > remaining {
+                d.ctx.save();
+                d.ctx
+                    .rectangle(x as f64, 0.0, remaining as f64, d.size.ht as f64);
+                d.ctx.clip();
+                self.draw_run(d, x, run);
+                d.ctx.restore();
+                remaining
+            } else {
+                self.draw_run(d, x, run)
+            };
+            if !run.actions.is_empty() {
+                regions.push(Region {
+                    x: (x - start) as f64,
+                    wd: wd as f64,
+                    actions: run.actions.clone(),
+                });
+            }
+            x += wd;
+        }
+        *self.regions.lock().unwrap() = regions;
+        total
+    }
+
+    /// The commands that should run for a click `x` pixels into the
+    /// widget. When actions are nested, the innermost one is last, and
+    /// they all get a look-in.
+    pub fn actions_at(&self, button: Button, x: f64) -> Vec<String> {
+        self.regions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| x >= r.x && x < r.x + r.wd)
+            .flat_map(|r| r.actions.iter())
+            .filter(|a| a.buttons.contains(&button))
+            .map(|a| a.command.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use crate::widgets::widget::{Overflow, Size};
+
+    /// An XBM file `width` pixels wide and one pixel tall, with every
+    /// pixel set
+    fn xbm(width: i32) -> String {
+        let bytes = vec!["0xff"; (width as usize).div_ceil(8)];
+        format!(
+            "#define icon_width {}\n#define icon_height 1\nstatic char icon_bits[] = {{ {} }};\n",
+            width,
+            bytes.join(", ")
+        )
+    }
+
+    /// Draw `path` with a fresh `Styled`, the way it happens after a
+    /// reload, returning how wide it came out
+    fn draw_icon(path: &Path) -> i32 {
+        let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, 100, 10).unwrap();
+        let ctx = cairo::Context::new(&surface);
+        let lyt = pangocairo::functions::create_layout(&ctx).unwrap();
+        let d = Drawing {
+            ctx: &ctx,
+            lyt: &lyt,
+            size: Size {
+                wd: 100,
+                ht: 10,
+                xo: 0,
+                yo: 0,
+            },
+            stdin: "",
+            buffer: 0.0,
+            limit: None,
+            overflow: Overflow::End,
+            markup: false,
+        };
+        let run = Run {
+            content: Content::Icon(path.to_path_buf()),
+            fg: None,
+            bg: None,
+            underline: false,
+            underline_color: None,
+            actions: Vec::new(),
+        };
+        Styled::new(None).draw(&d, Located::FromLeft(0), &[run.clone(), run])
+    }
+
+    #[test]
+    fn icon_surfaces_get_reused() {
+        let dir = TempDir::new("icons");
+        let path = dir.write("icon.xbm", xbm(5));
+        assert_eq!(draw_icon(&path), 10);
+        let cached = |path: &Path| {
+            SURFACES.with(|surfaces| surfaces.borrow().get(path).map(|s| s.get_width()))
+        };
+        assert_eq!(cached(&path), Some(5));
+
+        // a new config loads the icon again, in case it's changed
+        dir.write("icon.xbm", xbm(7));
+        assert_eq!(draw_icon(&path), 14);
+        assert_eq!(cached(&path), Some(7));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::widgets::options::KeyError;
+
+/// A little format string like `{used_gib}/{total_gib}`, split up
+/// ahead of time so that typos in key names get caught when we load
+/// the config rather than showing up as garbage in the bar
+#[derive(Debug, Clone)]
+pub struct Template {
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone)]
+enum Chunk {
+    Literal(String),
+    Key(String),
+}
+
+impl Template {
+    /// Split up `template`, which is the value of the config's `key`,
+    /// checking that it only asks for things in `keys`
+    pub fn parse(key: &str, template: &str, keys: &[&str]) -> Result<Template, KeyError> {
+        let error = |message| KeyError {
+            key: Some(key.to_string()),
+            message,
+        };
+        let mut chunks = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| error(format!("unclosed `{{` in {:?}", template)))?;
+            let name = &rest[start + 1..start + end];
+            if !keys.contains(&name) {
+                return Err(error(format!(
+                    "unknown key `{{{}}}` in {:?}: expected one of {}",
+                    name,
+                    template,
+                    keys.join(", ")
+                )));
+            }
+            if start > 0 {
+                chunks.push(Chunk::Literal(rest[..start].to_string()));
+            }
+            chunks.push(Chunk::Key(name.to_string()));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            chunks.push(Chunk::Literal(rest.to_string()));
+        }
+        Ok(Template { chunks })
+    }
+
+    pub fn render(&self, lookup: impl Fn(&str) -> String) -> String {
+        let mut text = String::new();
+        for chunk in self.chunks.iter() {
+            match chunk {
+                Chunk::Literal(s) => text.push_str(s),
+                Chunk::Key(k) => text.push_str(&lookup(k)),
+            }
+        }
+        text
+    }
+}
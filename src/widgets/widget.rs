@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy)]
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Size {
     pub wd: i32,
     pub ht: i32,
@@ -12,14 +14,78 @@ pub enum Located {
     FromRight(i32),
 }
 
+/// What to do with text that's wider than the room it's got
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    /// Cut off the beginning, and put an ellipsis there instead
+    Start,
+    /// Cut out the middle, and put an ellipsis there instead
+    Middle,
+    /// Cut off the end, and put an ellipsis there instead
+    End,
+    /// Scroll the text past, marquee-style
+    Scroll,
+}
+
+impl Overflow {
+    /// How fast scrolling text moves, in pixels per second
+    const SCROLL_SPEED: u128 = 40;
+    /// How much of a gap to leave between the end of scrolling text
+    /// and the next time it comes around
+    const SCROLL_GAP: i32 = 40;
+}
+
 impl Located {
     pub fn draw_text(self, d: &Drawing, msg: &str) -> i32 {
         use pango::LayoutExt;
-        d.lyt.set_text(msg);
+        d.set_text(msg);
+        // the layout gets shared between everything, so make sure
+        // we're not picking up limits from whoever used it last
+        d.lyt.set_width(-1);
+        d.lyt.set_ellipsize(pango::EllipsizeMode::None);
         let (w, _) = d.lyt.get_size();
-        d.ctx.move_to(self.target_x(d, w / pango::SCALE), d.buffer);
+        let mut w = w / pango::SCALE;
+
+        if let Some(limit) = d.limit.filter(|limit| w > *limit) {
+            let mode = match d.overflow {
+                Overflow::Scroll => return self.scroll_text(d, w, limit),
+                Overflow::Start => pango::EllipsizeMode::Start,
+                Overflow::Middle => pango::EllipsizeMode::Middle,
+                Overflow::End => pango::EllipsizeMode::End,
+            };
+            d.lyt.set_width(limit * pango::SCALE);
+            d.lyt.set_ellipsize(mode);
+            w = d.lyt.get_size().0 / pango::SCALE;
+        }
+
+        d.ctx.move_to(self.target_x(d, w), d.buffer);
         pangocairo::functions::show_layout(d.ctx, d.lyt);
-        w / pango::SCALE
+        w
+    }
+
+    /// Draw text that's `w` wide, but only `limit` of it at a time,
+    /// moving along a bit further every time we're drawn
+    fn scroll_text(self, d: &Drawing, w: i32, limit: i32) -> i32 {
+        let x = self.target_x(d, limit);
+        let period = (w + Overflow::SCROLL_GAP) as u128;
+        let millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let offset = (millis * Overflow::SCROLL_SPEED / 1000 % period) as f64;
+
+        d.ctx.save();
+        d.ctx.rectangle(x, 0.0, limit as f64, d.size.ht as f64);
+        d.ctx.clip();
+        // draw it twice, so that the start of the text follows on
+        // right after the end of it
+        for start in [x - offset, x - offset + period as f64].iter() {
+            d.ctx.move_to(*start, d.buffer);
+            pangocairo::functions::show_layout(d.ctx, d.lyt);
+        }
+        d.ctx.restore();
+        limit
     }
 
     pub fn target_x(self, d: &Drawing, w: i32) -> f64 {
@@ -30,20 +96,259 @@ impl Located {
     }
 }
 
+/// A mouse button, or a direction of the scroll wheel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    ScrollUp,
+    ScrollDown,
+    Other(i32),
+}
+
+impl Button {
+    /// Convert from the button numbers that X11 uses, where the
+    /// scroll wheel shows up as buttons 4 and 5
+    pub fn from_x11(button: i32) -> Button {
+        match button {
+            1 => Button::Left,
+            2 => Button::Middle,
+            3 => Button::Right,
+            4 => Button::ScrollUp,
+            5 => Button::ScrollDown,
+            n => Button::Other(n),
+        }
+    }
+
+    pub fn to_x11(self) -> i32 {
+        match self {
+            Button::Left => 1,
+            Button::Middle => 2,
+            Button::Right => 3,
+            Button::ScrollUp => 4,
+            Button::ScrollDown => 5,
+            Button::Other(n) => n,
+        }
+    }
+}
+
+/// How often a widget wants its `update` method called
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    /// Once per this long, measured from the previous update
+    Every(Duration),
+    /// Whenever the local wall-clock time is a multiple of this long:
+    /// e.g. a clock wants to be updated right at the top of each
+    /// minute, not somewhere in the middle of it
+    Aligned(Duration),
+}
+
+impl Frequency {
+    pub fn seconds(n: u64) -> Frequency {
+        Frequency::Every(Duration::from_secs(n))
+    }
+
+    /// Figure out when the next update should happen, given that the
+    /// last one was supposed to happen at `last`
+    pub fn next_deadline(self, last: Instant) -> Instant {
+        let now = Instant::now();
+        match self {
+            Frequency::Every(period) => {
+                // try to keep a steady rhythm, unless we've fallen
+                // so far behind that that's not possible
+                match last.checked_add(period) {
+                    Some(next) if next > now => next,
+                    _ => later(now, period),
+                }
+            }
+            Frequency::Aligned(period) => {
+                let offset = chrono::Local::now().offset().local_minus_utc() as i128;
+                let since_epoch = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos() as i128
+                    + offset * 1_000_000_000;
+                later(now, until_aligned(period, since_epoch))
+            }
+        }
+    }
+}
+
+/// How long it'll be until the next multiple of `period`, given that
+/// it's `since_epoch` nanoseconds past the (local) epoch now. Being
+/// right on one means waiting for the one after, so that we don't
+/// update twice in a row.
+fn until_aligned(period: Duration, since_epoch: i128) -> Duration {
+    let period = period.as_nanos().max(1);
+    let into_period = since_epoch.rem_euclid(period as i128) as u128;
+    Duration::from_nanos((period - into_period) as u64)
+}
+
+/// `now + wait`, except that a wait too long to even add on just
+/// turns into a very long one
+fn later(now: Instant, wait: Duration) -> Instant {
+    now.checked_add(wait)
+        .unwrap_or_else(|| now + Duration::from_secs(365 * 24 * 60 * 60))
+}
+
 pub struct Drawing<'t> {
     pub ctx: &'t cairo::Context,
     pub lyt: &'t pango::Layout,
     pub size: Size,
     pub stdin: &'t str,
     pub buffer: f64,
+    // how much room the widget's got, if it's been limited, and what
+    // to do with text that won't fit in it
+    pub limit: Option<i32>,
+    pub overflow: Overflow,
+    // whether text should be treated as Pango markup
+    pub markup: bool,
+}
+
+impl<'t> Drawing<'t> {
+    /// Put some text in the layout, as markup if that's what this
+    /// widget wants. Markup that doesn't parse gets shown as-is,
+    /// because seeing the tags is better than seeing nothing.
+    pub fn set_text(&self, msg: &str) {
+        use pango::LayoutExt;
+        if self.markup {
+            if let Ok((attrs, text, _)) = pango::parse_markup(msg, '\0') {
+                self.lyt.set_text(&text);
+                self.lyt.set_attributes(&attrs);
+                return;
+            }
+        }
+        self.lyt.set_text(msg);
+        self.lyt.set_attributes(None);
+    }
+
+    /// Make a string that came from somewhere else (like a song
+    /// title) safe to put into markup, if we're using markup
+    pub fn escape<'a>(&self, s: &'a str) -> std::borrow::Cow<'a, str> {
+        if self.markup {
+            escape_markup(s).into()
+        } else {
+            s.into()
+        }
+    }
 }
 
-pub trait Widget {
-    fn update_frequency(&self) -> Option<u64> {
+/// Escape the characters that mean something in Pango markup
+pub fn escape_markup(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Widgets can get updated on their own worker threads while the
+/// main thread carries on drawing them, so anything that changes on
+/// update needs to live behind a lock.
+pub trait Widget: Send + Sync {
+    fn update_frequency(&self) -> Option<Frequency> {
+        None
+    }
+
+    /// Whether `update` does anything slow, like reading files or
+    /// talking to the network. Widgets that say so get a thread of
+    /// their own to update on, and everything else gets updated right
+    /// there in the main loop.
+    fn update_blocks(&self) -> bool {
+        false
+    }
+
+    /// Refresh whatever this widget displays. If `update_blocks` says
+    /// so, this happens off the main thread, so it's fine for it to
+    /// block on slow things like the network.
+    fn update(&self) {}
+
+    /// A file descriptor that, when it becomes readable, means this
+    /// widget has something new to show
+    fn fd(&self) -> Option<std::os::unix::io::RawFd> {
         None
     }
 
-    fn update(&mut self) {}
+    /// Called from the main loop whenever the widget's `fd` is
+    /// readable
+    fn on_readable(&self) {}
+
+    /// Called when the widget is clicked, with the position of the
+    /// click relative to the top-left of where the widget was drawn
+    fn on_click(&self, _button: Button, _x: f64, _y: f64) {}
+
+    /// Replace the text this widget is showing, for the widgets where
+    /// that makes sense. This is what `knurling msg set` ends up calling.
+    fn set_text(&self, _text: &str) -> Result<(), failure::Error> {
+        bail!("this widget doesn't accept text")
+    }
+
+    /// Other widgets that should get drawn alongside this one in
+    /// different parts of the bar, for things like lemonbar input
+    /// that can put text anywhere
+    fn companions(&self) -> Vec<(crate::config::Section, Box<dyn Widget>)> {
+        Vec::new()
+    }
 
     fn draw(&self, d: &Drawing, loc: Located) -> i32;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: i128 = 1_000_000_000;
+
+    #[test]
+    fn aligned_to_the_period() {
+        let minute = Duration::from_secs(60);
+        assert_eq!(until_aligned(minute, 90 * SEC), Duration::from_secs(30));
+        assert_eq!(until_aligned(minute, 120 * SEC), minute);
+        assert_eq!(
+            until_aligned(minute, 119 * SEC + 1),
+            Duration::new(0, 999_999_999)
+        );
+        // west of Greenwich, local time starts out before the epoch
+        assert_eq!(until_aligned(minute, -10 * SEC), Duration::from_secs(10));
+        // a zero period can't be aligned to, but it shouldn't crash
+        assert_eq!(
+            until_aligned(Duration::from_secs(0), 5 * SEC),
+            Duration::from_nanos(1)
+        );
+    }
+
+    #[test]
+    fn every_keeps_a_steady_rhythm() {
+        let five = Duration::from_secs(5);
+        let last = Instant::now();
+        assert_eq!(Frequency::Every(five).next_deadline(last), last + five);
+
+        // if we've fallen well behind, start counting again from now
+        let before = Instant::now();
+        let long_ago = before
+            .checked_sub(Duration::from_secs(60))
+            .unwrap_or(before);
+        assert!(Frequency::Every(five).next_deadline(long_ago) >= before + five);
+    }
+
+    #[test]
+    fn escaping_markup() {
+        assert_eq!(escape_markup("plain text"), "plain text");
+        assert_eq!(
+            escape_markup(r#"<b>"Tom" & 'Jerry'</b>"#),
+            "&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;"
+        );
+        // and Pango reads it back as exactly what we started with
+        let text = "a < b && c > 'd' <span>";
+        let (_, parsed, _) = pango::parse_markup(&escape_markup(text), '\0').unwrap();
+        assert_eq!(parsed.as_str(), text);
+    }
+}
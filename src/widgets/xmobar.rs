@@ -0,0 +1,251 @@
+use crate::widgets::styled::{push_run, valid_color, Action, Content, Run};
+use crate::widgets::widget::Button;
+
+/// Parse a line of xmobar-style markup into runs of styled text. This
+/// understands `<fc>`, `<action>`, `<icon>`, `<hspace>` and `<raw>`;
+/// other tags xmobar knows about (like `<fn>` and `<box>`) get
+/// dropped, and anything that doesn't look like a tag at all is left
+/// as text.
+pub fn parse(input: &str) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    let mut colors: Vec<(Option<String>, Option<String>)> = Vec::new();
+    let mut actions: Vec<Action> = Vec::new();
+
+    let push = |runs: &mut Vec<Run>, content: Content, colors: &[_], actions: &[Action]| {
+        let (fg, bg) = colors.last().cloned().unwrap_or((None, None));
+        push_run(
+            runs,
+            Run {
+                content,
+                fg,
+                bg,
+                underline: false,
+                underline_color: None,
+                actions: actions.to_vec(),
+            },
+        );
+    };
+
+    let mut rest = input;
+    while !rest.is_empty() {
+        let tag = if rest.starts_with('<') {
+            Tag::parse(rest)
+        } else {
+            None
+        };
+        match tag {
+            Some((tag, len)) => {
+                match tag {
+                    Tag::Color(fg, bg) => colors.push((fg, bg)),
+                    Tag::EndColor => {
+                        colors.pop();
+                    }
+                    Tag::Action(action) => actions.push(action),
+                    Tag::EndAction => {
+                        actions.pop();
+                    }
+                    Tag::Icon(path) => {
+                        push(&mut runs, Content::Icon(path.into()), &colors, &actions)
+                    }
+                    Tag::Space(wd) => push(&mut runs, Content::Space(wd), &colors, &actions),
+                    Tag::Raw(text) => push(&mut runs, Content::Text(text), &colors, &actions),
+                    Tag::Ignored => (),
+                }
+                rest = &rest[len..];
+            }
+            None => {
+                // everything up to the next thing that might be a tag
+                // is just text
+                let end = rest
+                    .char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| c == '<')
+                    .map_or(rest.len(), |(i, _)| i);
+                push(
+                    &mut runs,
+                    Content::Text(rest[..end].to_string()),
+                    &colors,
+                    &actions,
+                );
+                rest = &rest[end..];
+            }
+        }
+    }
+    runs
+}
+
+enum Tag {
+    Color(Option<String>, Option<String>),
+    EndColor,
+    Action(Action),
+    EndAction,
+    Icon(String),
+    Space(i32),
+    Raw(String),
+    Ignored,
+}
+
+impl Tag {
+    /// Try to read a tag from the start of `s`, returning it along
+    /// with how many bytes of `s` it took up
+    fn parse(s: &str) -> Option<(Tag, usize)> {
+        if let Some(body) = s.strip_prefix("<action=") {
+            return Tag::action(body).map(|(tag, len)| (tag, len + "<action=".len()));
+        }
+        if let Some(body) = s.strip_prefix("<raw=") {
+            return Tag::raw(body).map(|(tag, len)| (tag, len + "<raw=".len()));
+        }
+
+        let end = s.find('>')?;
+        let tag = &s[1..end];
+        let len = end + 1;
+        let parsed = if let Some(spec) = tag.strip_prefix("fc=") {
+            // this can be `fg`, `fg,bg`, or either of those with a
+            // `:offset` on the end, which we don't do anything with
+            let spec = spec.split(':').next().unwrap_or(spec);
+            let mut colors = spec.splitn(2, ',').map(|c| {
+                let c = c.trim();
+                if valid_color(c) {
+                    Some(c.to_string())
+                } else {
+                    None
+                }
+            });
+            Tag::Color(colors.next().flatten(), colors.next().flatten())
+        } else if tag == "/fc" {
+            Tag::EndColor
+        } else if tag == "/action" {
+            Tag::EndAction
+        } else if let Some(path) = tag.strip_prefix("icon=") {
+            Tag::Icon(path.strip_suffix('/').unwrap_or(path).to_string())
+        } else if let Some(wd) = tag.strip_prefix("hspace=") {
+            Tag::Space(wd.strip_suffix('/').unwrap_or(wd).parse().ok()?)
+        } else if ["fn=", "/fn", "box", "/box", "p="]
+            .iter()
+            .any(|prefix| tag.starts_with(prefix))
+        {
+            Tag::Ignored
+        } else {
+            return None;
+        };
+        Some((parsed, len))
+    }
+
+    /// Read the rest of an `<action=...>` tag, which looks like
+    /// ``<action=`command` button=13>``, where the backticks and the
+    /// buttons are both optional
+    fn action(body: &str) -> Option<(Tag, usize)> {
+        let (command, rest, used) = if let Some(quoted) = body.strip_prefix('`') {
+            let end = quoted.find('`')?;
+            (&quoted[..end], &quoted[end + 1..], end + 2)
+        } else {
+            let end = body.find([' ', '>'])?;
+            (&body[..end], &body[end..], end)
+        };
+        let end = rest.find('>')?;
+        let buttons = match rest[..end].trim().strip_prefix("button=") {
+            Some(digits) => digits
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .map(|n| Button::from_x11(n as i32))
+                .collect(),
+            None => vec![Button::Left],
+        };
+        Some((
+            Tag::Action(Action {
+                command: command.to_string(),
+                buttons,
+            }),
+            used + end + 1,
+        ))
+    }
+
+    /// Read the rest of a `<raw=len:text/>` tag, where the text is
+    /// taken as-is even if it's got things in it that look like tags
+    fn raw(body: &str) -> Option<(Tag, usize)> {
+        let colon = body.find(':')?;
+        let len: usize = body[..colon].parse().ok()?;
+        let text = &body[colon + 1..];
+        let end = text.char_indices().nth(len).map_or(text.len(), |(i, _)| i);
+        if text[..end].chars().count() != len || !text[end..].starts_with("/>") {
+            return None;
+        }
+        Some((Tag::Raw(text[..end].to_string()), colon + 1 + end + 2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str, fg: Option<&str>, bg: Option<&str>, actions: Vec<Action>) -> Run {
+        Run {
+            content: Content::Text(s.to_string()),
+            fg: fg.map(str::to_string),
+            bg: bg.map(str::to_string),
+            underline: false,
+            underline_color: None,
+            actions,
+        }
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(parse("a < b"), vec![text("a < b", None, None, vec![])]);
+        assert_eq!(parse(""), vec![]);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            parse("<fc=#ff0000,blue:2>red</fc> <fc=notacolor>x</fc>"),
+            vec![
+                text("red", Some("#ff0000"), Some("blue"), vec![]),
+                text(" x", None, None, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn actions() {
+        let vol = Action {
+            command: "pamixer -i 5".to_string(),
+            buttons: vec![Button::ScrollUp, Button::ScrollDown],
+        };
+        let menu = Action {
+            command: "menu".to_string(),
+            buttons: vec![Button::Left],
+        };
+        assert_eq!(
+            parse("<action=`pamixer -i 5` button=45>vol</action><action=menu>m</action>"),
+            vec![
+                text("vol", None, None, vec![vol]),
+                text("m", None, None, vec![menu])
+            ]
+        );
+    }
+
+    #[test]
+    fn other_tags() {
+        assert_eq!(
+            parse("<fn=1>a</fn><hspace=4/><icon=/tmp/x.xbm/><raw=3:<b>/>"),
+            vec![
+                text("a", None, None, vec![]),
+                Run {
+                    content: Content::Space(4),
+                    ..text("", None, None, vec![])
+                },
+                Run {
+                    content: Content::Icon("/tmp/x.xbm".into()),
+                    ..text("", None, None, vec![])
+                },
+                text("<b>", None, None, vec![]),
+            ]
+        );
+        // a raw tag whose length is wrong is just text
+        assert_eq!(
+            parse("<raw=9:ab/>"),
+            vec![text("<raw=9:ab/>", None, None, vec![])]
+        );
+    }
+}
@@ -1,6 +1,7 @@
-use x11::{xinput2, xlib};
+use x11::{xinput2, xlib, xrandr};
 
-use std::ffi::CString;
+use cairo::prelude::SurfaceExt;
+use std::ffi::{CStr, CString};
 use std::os::raw::{c_int, c_uchar};
 use std::{mem, ptr};
 
@@ -9,6 +10,13 @@ use crate::widgets::Size;
 pub struct Display {
     pub display: *mut xlib::_XDisplay,
     pub screen: i32,
+    // where XRandR's events start, if the server has it
+    randr_event_base: Option<c_int>,
+    // these two are interned strings kept around because we want to
+    // check against them a _lot_, to find out if an event is a quit
+    // event
+    pub wm_protocols: u64,
+    pub wm_delete_window: u64,
 }
 
 impl Display {
@@ -18,36 +26,234 @@ impl Display {
             bail!("Unable to open X11 display");
         }
         let screen = unsafe { xlib::XDefaultScreen(display) };
-        Ok(Display { display, screen })
+        let (mut event_base, mut error_base) = (0, 0);
+        let randr_event_base =
+            match unsafe { xrandr::XRRQueryExtension(display, &mut event_base, &mut error_base) } {
+                0 => None,
+                _ => Some(event_base),
+            };
+        let (wm_protocols, wm_delete_window) = unsafe {
+            let protocols = CString::new("WM_PROTOCOLS")?;
+            let delete_window = CString::new("WM_DELETE_WINDOW")?;
+            (
+                xlib::XInternAtom(display, protocols.as_ptr(), 0),
+                xlib::XInternAtom(display, delete_window.as_ptr(), 0),
+            )
+        };
+        Ok(Display {
+            display,
+            screen,
+            randr_event_base,
+            wm_protocols,
+            wm_delete_window,
+        })
+    }
+
+    /// Ask to hear about monitors being plugged in, unplugged, or
+    /// changing resolution. These turn up as `Event::ScreenEvent`s.
+    pub fn select_screen_changes(&self) {
+        if self.randr_event_base.is_none() {
+            return;
+        }
+        unsafe {
+            xrandr::XRRSelectInput(
+                self.display,
+                xlib::XRootWindow(self.display, self.screen),
+                xrandr::RRScreenChangeNotifyMask
+                    | xrandr::RRCrtcChangeNotifyMask
+                    | xrandr::RROutputChangeNotifyMask,
+            );
+        }
     }
 
-    pub fn get_width(&mut self) -> i32 {
+    /// handle a single event, wrapping it as an 'Event'. This is
+    /// pretty useless right now, but the plan is to make it easier to
+    /// handle things like keyboard input and mouse input later. This
+    /// will also only return values for events we care about. Events
+    /// for every window come through here, so anything about one in
+    /// particular says which window it's for
+    pub fn handle(&self) -> Option<Event> {
+        let mut e = mem::MaybeUninit::uninit();
+        unsafe {
+            xlib::XNextEvent(self.display, e.as_mut_ptr());
+            e.assume_init();
+        }
+        // XRandR's events don't have fixed numbers, so they can't go
+        // in the match below
+        if let Some(base) = self.randr_event_base {
+            let typ = unsafe { *e.as_ptr() }.get_type();
+            if typ == base + xrandr::RRScreenChangeNotify {
+                // this keeps Xlib's idea of how big the screen is up
+                // to date
+                unsafe { xrandr::XRRUpdateConfiguration(e.as_mut_ptr()) };
+                return Some(Event::ScreenEvent);
+            }
+            if typ == base + xrandr::RRNotify {
+                return Some(Event::ScreenEvent);
+            }
+        }
+        match unsafe { *e.as_ptr() }.get_type() {
+            // Is it a quit event? We gotta do some tedious string
+            // comparison to find out
+            xlib::ClientMessage => {
+                let xclient: xlib::XClientMessageEvent = unsafe { From::from(*e.as_ptr()) };
+                if xclient.message_type == self.wm_protocols && xclient.format == 32 {
+                    let protocol = xclient.data.get_long(0) as xlib::Atom;
+                    if protocol == self.wm_delete_window {
+                        return Some(Event::QuitEvent);
+                    }
+                }
+            }
+
+            // Is it a show event?
+            xlib::Expose => return Some(Event::ShowEvent),
+
+            // otherwise, it might be a mouse press event
+            xlib::GenericEvent => {
+                let mut cookie: xlib::XGenericEventCookie = unsafe { From::from(*e.as_ptr()) };
+                unsafe { xlib::XGetEventData(self.display, &mut cookie) };
+                let mut event = None;
+                if let xinput2::XI_ButtonPress = cookie.evtype {
+                    let data: &xinput2::XIDeviceEvent =
+                        unsafe { &*(cookie.data as *const xinput2::XIDeviceEvent) };
+                    event = Some(Event::MouseEvent {
+                        window: data.event,
+                        button: data.detail,
+                        x: data.event_x,
+                        y: data.event_y,
+                    });
+                }
+                unsafe { xlib::XFreeEventData(self.display, &mut cookie) };
+                return event;
+            }
+            _ => (),
+        }
+
+        None
+    }
+
+    /// True if there are any pending events.
+    pub fn has_events(&self) -> bool {
+        unsafe { xlib::XPending(self.display) != 0 }
+    }
+
+    /// Did you know that X11 uses a file descriptor underneath the
+    /// surface to wait on events? This lets us use select on it! It's
+    /// the connection's, not any one window's, so it's still worth
+    /// waiting on when there are no windows at all: that's when we
+    /// find out a monitor's come back.
+    pub fn get_fd(&self) -> i32 {
+        unsafe { xlib::XConnectionNumber(self.display) }
+    }
+
+    pub fn get_width(&self) -> i32 {
         unsafe {
             let s = xlib::XScreenOfDisplay(self.display, self.screen);
             xlib::XWidthOfScreen(s)
         }
     }
 
-    pub fn get_widths(&mut self) -> Result<Vec<(i32, i32)>, failure::Error> {
+    pub fn get_height(&self) -> i32 {
+        unsafe {
+            let s = xlib::XScreenOfDisplay(self.display, self.screen);
+            xlib::XHeightOfScreen(s)
+        }
+    }
+
+    /// Where each monitor is. XRandR can tell us what they're called
+    /// as well, but if it's too old then Xinerama will do, and without
+    /// either of them it's just the whole screen.
+    pub fn get_monitors(&self) -> Result<Vec<Monitor>, failure::Error> {
+        if let Some(monitors) = self.get_randr_monitors().filter(|m| !m.is_empty()) {
+            return Ok(monitors);
+        }
         if unsafe { x11::xinerama::XineramaIsActive(self.display) != 0 } {
             let mut screens = 0;
             let screen_info =
                 unsafe { x11::xinerama::XineramaQueryScreens(self.display, &mut screens) };
-            let mut widths = Vec::new();
+            let mut monitors = Vec::new();
             for i in 0..screens {
                 unsafe {
                     let si = screen_info
                         .offset(i as isize)
                         .as_ref()
                         .ok_or_else(|| format_err!("bad pointer"))?;
-                    widths.push((si.x_org as i32, si.width as i32));
+                    monitors.push(Monitor {
+                        name: None,
+                        primary: i == 0,
+                        x: si.x_org as i32,
+                        y: si.y_org as i32,
+                        width: si.width as i32,
+                        height: si.height as i32,
+                    });
                 }
             }
-            Ok(widths)
+            Ok(monitors)
         } else {
-            Ok(vec![(0, self.get_width())])
+            Ok(vec![Monitor {
+                name: None,
+                primary: true,
+                x: 0,
+                y: 0,
+                width: self.get_width(),
+                height: self.get_height(),
+            }])
         }
     }
+
+    /// Monitors as XRandR sees them, which needs at least version
+    /// 1.5 of the extension
+    fn get_randr_monitors(&self) -> Option<Vec<Monitor>> {
+        self.randr_event_base?;
+        unsafe {
+            let (mut major, mut minor) = (0, 0);
+            xrandr::XRRQueryVersion(self.display, &mut major, &mut minor);
+            if (major, minor) < (1, 5) {
+                return None;
+            }
+
+            let root = xlib::XRootWindow(self.display, self.screen);
+            let mut count = 0;
+            let info = xrandr::XRRGetMonitors(self.display, root, xlib::True, &mut count);
+            if info.is_null() {
+                return None;
+            }
+            let mut monitors = Vec::new();
+            for i in 0..count {
+                let m = &*info.offset(i as isize);
+                let name_ptr = xlib::XGetAtomName(self.display, m.name);
+                let name = if name_ptr.is_null() {
+                    None
+                } else {
+                    let name = CStr::from_ptr(name_ptr).to_string_lossy().into_owned();
+                    xlib::XFree(name_ptr as *mut _);
+                    Some(name)
+                };
+                monitors.push(Monitor {
+                    name,
+                    primary: m.primary != 0,
+                    x: m.x,
+                    y: m.y,
+                    width: m.width,
+                    height: m.height,
+                });
+            }
+            xrandr::XRRFreeMonitors(info);
+            Some(monitors)
+        }
+    }
+}
+
+/// The position and size of one monitor, in root window coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monitor {
+    // the name of the output it's on (e.g. `DP-1`), if we know it
+    pub name: Option<String>,
+    pub primary: bool,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
 }
 
 impl Drop for Display {
@@ -64,11 +270,6 @@ pub struct Window<'t> {
     pub display: &'t Display,
     pub screen: i32,
     pub window: u64,
-    // these two are interned strings kept around because we want to
-    // check against them a _lot_, to find out if an event is a quit
-    // event
-    pub wm_protocols: u64,
-    pub wm_delete_window: u64,
     // The width and height of the window
     pub width: i32,
     pub height: i32,
@@ -91,28 +292,18 @@ impl<'t> Window<'t> {
             let window = xlib::XCreateSimpleWindow(
                 display.display,
                 xlib::XRootWindow(display.display, screen),
-                xo as i32,
-                yo as i32,
+                xo,
+                yo,
                 width as u32,
                 height as u32,
                 1,
                 xlib::XBlackPixel(display.display, screen),
                 xlib::XWhitePixel(display.display, screen),
             );
-            let wm_protocols = {
-                let cstr = CString::new("WM_PROTOCOLS")?;
-                xlib::XInternAtom(display.display, cstr.as_ptr(), 0)
-            };
-            let wm_delete_window = {
-                let cstr = CString::new("WM_DELETE_WINDOW")?;
-                xlib::XInternAtom(display.display, cstr.as_ptr(), 0)
-            };
             Ok(Window {
                 display,
                 screen,
                 window,
-                wm_protocols,
-                wm_delete_window,
                 width,
                 height,
             })
@@ -159,7 +350,7 @@ impl<'t> Window<'t> {
     }
 
     pub fn set_protocols(&mut self) -> Result<(), failure::Error> {
-        let mut protocols = [self.intern("WM_DELETE_WINDOW")?];
+        let mut protocols = [self.display.wm_delete_window];
         unsafe {
             xlib::XSetWMProtocols(
                 self.display.display,
@@ -190,6 +381,39 @@ impl<'t> Window<'t> {
         }
     }
 
+    /// Take the window off the screen without destroying it
+    pub fn unmap(&mut self) {
+        unsafe {
+            xlib::XUnmapWindow(self.display.display, self.window);
+        }
+    }
+
+    /// Move and resize the window, e.g. because the font got bigger
+    /// or the bar moved to the other end of the screen. Any existing
+    /// Cairo surfaces will need recreating.
+    pub fn set_geometry(
+        &mut self,
+        Size {
+            wd: width,
+            ht: height,
+            xo,
+            yo,
+        }: Size,
+    ) {
+        self.width = width;
+        self.height = height;
+        unsafe {
+            xlib::XMoveResizeWindow(
+                self.display.display,
+                self.window,
+                xo,
+                yo,
+                width as u32,
+                height as u32,
+            );
+        }
+    }
+
     /// Intern a string in the x server
     pub fn intern(&mut self, s: &str) -> Result<u64, failure::Error> {
         unsafe {
@@ -238,68 +462,91 @@ impl<'t> Window<'t> {
         }
     }
 
-    /// handle a single event, wrapping it as an 'Event'. This is
-    /// pretty useless right now, but the plan is to make it easier to
-    /// handle things like keyboard input and mouse input later. This
-    /// will also only return values for events we care about
-    pub fn handle(&mut self) -> Option<Event> {
-        let mut e = mem::MaybeUninit::uninit();
-        unsafe {
-            xlib::XNextEvent(self.display.display, e.as_mut_ptr());
-            e.assume_init();
+    pub fn size(&self) -> Size {
+        Size {
+            wd: self.width,
+            ht: self.height,
+            xo: 0,
+            yo: 0,
         }
-        match unsafe { *e.as_ptr() }.get_type() {
-            // Is it a quit event? We gotta do some tedious string
-            // comparison to find out
-            xlib::ClientMessage => {
-                let xclient: xlib::XClientMessageEvent = unsafe { From::from(*e.as_ptr()) };
-                if xclient.message_type == self.wm_protocols && xclient.format == 32 {
-                    let protocol = xclient.data.get_long(0) as xlib::Atom;
-                    if protocol == self.wm_delete_window {
-                        return Some(Event::QuitEvent);
-                    }
-                }
-            }
+    }
+}
 
-            // Is it a show event?
-            xlib::Expose => return Some(Event::ShowEvent),
+/// Somewhere to draw the bar before it goes on a window. Drawing
+/// straight onto the window means it can get shown half-finished,
+/// which flickers, so instead each frame gets drawn here and then
+/// copied over in one go. Most frames come out the same as the one
+/// before, so those don't get copied at all.
+pub struct Canvas {
+    display: *mut xlib::_XDisplay,
+    window: cairo::Context,
+    surface: cairo::ImageSurface,
+    ctx: cairo::Context,
+    // a copy of what's on the window right now
+    shown: Vec<u8>,
+}
 
-            // otherwise, it might be a mouse press event
-            xlib::GenericEvent => {
-                let mut cookie: xlib::XGenericEventCookie = unsafe { From::from(*e.as_ptr()) };
-                unsafe { xlib::XGetEventData(self.display.display, &mut cookie) };
-                if let xinput2::XI_ButtonPress = cookie.evtype {
-                    let data: &xinput2::XIDeviceEvent =
-                        unsafe { &*(cookie.data as *const xinput2::XIDeviceEvent) };
-                    return Some(Event::MouseEvent {
-                        x: data.event_x,
-                        y: data.event_y,
-                    });
-                }
-            }
-            _ => (),
-        }
+impl Canvas {
+    pub fn new(w: &mut Window) -> Result<Canvas, failure::Error> {
+        let window = cairo::Context::new(&w.get_cairo_surface());
+        let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, w.width, w.height)
+            .map_err(|err| format_err!("Unable to create drawing surface: {:?}", err))?;
+        let ctx = cairo::Context::new(&surface);
+        Ok(Canvas {
+            display: w.display.display,
+            window,
+            surface,
+            ctx,
+            shown: Vec::new(),
+        })
+    }
 
-        None
+    /// The context to draw the next frame with
+    pub fn context(&self) -> &cairo::Context {
+        &self.ctx
     }
 
-    /// True if there are any pending events.
-    pub fn has_events(&mut self) -> bool {
-        unsafe { xlib::XPending(self.display.display) != 0 }
+    /// Forget what's on the window, so the next frame gets copied
+    /// over whether it's changed or not, e.g. because the window got
+    /// covered up and needs drawing again
+    pub fn invalidate(&mut self) {
+        self.shown.clear();
     }
 
-    /// Did you know that X11 uses a file descriptor underneath the
-    /// surface to wait on events? This lets us use select on it!
-    pub fn get_fd(&mut self) -> i32 {
-        unsafe { xlib::XConnectionNumber(self.display.display) }
+    /// Put the frame that's just been drawn onto the window, if it's
+    /// any different from what's there already
+    pub fn show(&mut self) {
+        self.surface.flush();
+        let len = (self.surface.get_stride() * self.surface.get_height()) as usize;
+        let frame = unsafe {
+            let data = cairo_sys::cairo_image_surface_get_data(self.surface.to_raw_none());
+            if data.is_null() {
+                return;
+            }
+            std::slice::from_raw_parts(data, len)
+        };
+        if frame == &self.shown[..] {
+            return;
+        }
+        self.shown.clear();
+        self.shown.extend_from_slice(frame);
+
+        self.window.set_source_surface(&self.surface, 0.0, 0.0);
+        self.window.paint();
+        self.window.get_target().flush();
+        unsafe {
+            xlib::XFlush(self.display);
+        }
     }
+}
 
-    pub fn size(&self) -> Size {
-        Size {
-            wd: self.width,
-            ht: self.height,
-            xo: 0,
-            yo: 0,
+/// A window's taken off the screen for good once we've finished
+/// with it, e.g. because its monitor got unplugged. Anything drawing
+/// on it has to be gone by then.
+impl Drop for Window<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XDestroyWindow(self.display.display, self.window);
         }
     }
 }
@@ -340,8 +587,16 @@ impl XProperty for &str {
 /// An ADT of only the events we care about, wrapped in a high-level
 /// way
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Event {
-    MouseEvent { x: f64, y: f64 },
+    MouseEvent {
+        window: u64,
+        button: i32,
+        x: f64,
+        y: f64,
+    },
     ShowEvent,
+    // the monitors changed
+    ScreenEvent,
     QuitEvent,
 }
@@ -0,0 +1,112 @@
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use crate::widgets::Widget;
+
+/// A pipe that worker threads poke whenever they finish an update:
+/// the read end lives in the main loop's `select` set, so that new
+/// results get drawn right away
+pub struct Waker {
+    read_fd: RawFd,
+    write_fd: RawFd,
+}
+
+impl Waker {
+    pub fn new() -> Result<Waker, failure::Error> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } != 0 {
+            bail!(
+                "Unable to create wakeup pipe: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(Waker {
+            read_fd: fds[0],
+            write_fd: fds[1],
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.read_fd
+    }
+
+    pub fn wake(&self) {
+        // if the pipe's full then there's already a wakeup pending,
+        // so it's fine if this doesn't go through
+        unsafe {
+            libc::write(self.write_fd, [0u8].as_ptr() as *const libc::c_void, 1);
+        }
+    }
+
+    /// Empty out the pipe so that `select` doesn't keep waking us
+    pub fn drain(&self) {
+        let mut buf = [0u8; 64];
+        while unsafe {
+            libc::read(
+                self.read_fd,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        } > 0
+        {}
+    }
+}
+
+impl Drop for Waker {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read_fd);
+            libc::close(self.write_fd);
+        }
+    }
+}
+
+/// A thread dedicated to updating a single widget, so that a widget
+/// stuck on something slow can't hold up anything else
+pub struct Worker {
+    requests: mpsc::Sender<()>,
+    busy: Arc<AtomicBool>,
+    requested_at: Instant,
+}
+
+impl Worker {
+    pub fn spawn(widget: Arc<dyn Widget>, waker: Arc<Waker>) -> Worker {
+        let (requests, incoming) = mpsc::channel();
+        let busy = Arc::new(AtomicBool::new(false));
+        let thread_busy = busy.clone();
+        // this keeps going until the `Worker` gets dropped, at which
+        // point the channel closes and the loop ends
+        std::thread::spawn(move || {
+            for () in incoming.iter() {
+                widget.update();
+                thread_busy.store(false, Ordering::SeqCst);
+                waker.wake();
+            }
+        });
+        Worker {
+            requests,
+            busy,
+            requested_at: Instant::now(),
+        }
+    }
+
+    /// Ask for an update, unless the previous one is still going
+    pub fn request(&mut self) {
+        if !self.busy.swap(true, Ordering::SeqCst) {
+            self.requested_at = Instant::now();
+            let _ = self.requests.send(());
+        }
+    }
+
+    /// If there's an update in progress, the point at which it'll
+    /// have been going on for longer than `timeout`
+    pub fn overdue_at(&self, timeout: Duration) -> Option<Instant> {
+        if self.busy.load(Ordering::SeqCst) {
+            Some(self.requested_at + timeout)
+        } else {
+            None
+        }
+    }
+}